@@ -0,0 +1,351 @@
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::OutputPin;
+
+/// Memory as seen by the interpreter
+///
+/// Every read and write of chip8 memory, including instruction fetches,
+/// goes through a Bus. Reads take `&mut self` so an implementation can
+/// talk to external memory or give reads side effects for memory mapped I/O.
+pub trait Bus {
+    /// Returns the number of addressable bytes
+    fn len(&self) -> usize;
+
+    /// Reads the byte at `address`
+    fn read(&mut self, address: usize) -> u8;
+
+    /// Writes `value` to `address`
+    fn write(&mut self, address: usize, value: u8);
+
+    /// Returns true when the bus has no addressable bytes
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Reads consecutive bytes starting at `address` into `buffer`
+    fn read_slice(&mut self, address: usize, buffer: &mut [u8]) {
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.read(address + i);
+        }
+    }
+
+    /// Writes consecutive bytes starting at `address`
+    fn write_slice(&mut self, address: usize, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            self.write(address + i, *byte);
+        }
+    }
+
+    /// Sets every byte of the bus to `value`
+    fn fill(&mut self, value: u8) {
+        for address in 0..self.len() {
+            self.write(address, value);
+        }
+    }
+
+    /// Returns the whole memory as a slice if it is directly addressable
+    fn as_slice(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Reads the byte at `address` without side effects, or `None`
+    /// when the bus cannot be read without `&mut self`
+    ///
+    /// The default reads through `as_slice`
+    fn peek(&self, address: usize) -> Option<u8> {
+        let memory = self.as_slice()?;
+        memory.get(address.checked_rem(memory.len())?).copied()
+    }
+
+    /// Returns true when an access has failed, the interpreter
+    /// checks this after every instruction and halts on a fault
+    fn faulted(&self) -> bool {
+        false
+    }
+}
+
+/// Plain internal RAM of `N` bytes, 4K by default
+///
+/// Addresses past the end wrap around to the start, so a program
+/// that runs off the end of memory cannot panic the interpreter.
+pub struct Ram<const N: usize = 4096> {
+    data: [u8; N],
+}
+
+impl<const N: usize> Ram<N> {
+    /// Creates a zeroed Ram
+    pub const fn new() -> Self {
+        Self { data: [0; N] }
+    }
+}
+
+impl<const N: usize> Default for Ram<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Bus for Ram<N> {
+    fn len(&self) -> usize {
+        N
+    }
+
+    fn read(&mut self, address: usize) -> u8 {
+        self.data[address % N]
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        self.data[address % N] = value;
+    }
+
+    fn read_slice(&mut self, address: usize, buffer: &mut [u8]) {
+        match address.checked_add(buffer.len()) {
+            Some(end) if end <= N => buffer.copy_from_slice(&self.data[address..end]),
+            _ => {
+                for (i, byte) in buffer.iter_mut().enumerate() {
+                    *byte = self.data[address.wrapping_add(i) % N];
+                }
+            }
+        }
+    }
+
+    fn write_slice(&mut self, address: usize, data: &[u8]) {
+        match address.checked_add(data.len()) {
+            Some(end) if end <= N => self.data[address..end].copy_from_slice(data),
+            _ => {
+                for (i, byte) in data.iter().enumerate() {
+                    self.data[address.wrapping_add(i) % N] = *byte;
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self, value: u8) {
+        self.data = [value; N];
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(&self.data)
+    }
+}
+
+/// A bus with a peripheral mapped over part of its address space
+///
+/// Accesses between `start` and `start + peripheral.len()` go to the
+/// peripheral at an offset from `start`, everything else goes to the
+/// underlying bus. Several peripherals can be mapped by nesting MappedBus.
+pub struct MappedBus<B, P>
+where
+    B: Bus,
+    P: Bus,
+{
+    bus: B,
+    start: usize,
+    peripheral: P,
+}
+
+impl<B, P> MappedBus<B, P>
+where
+    B: Bus,
+    P: Bus,
+{
+    /// Maps `peripheral` over `bus` starting at `start`
+    pub fn new(bus: B, start: usize, peripheral: P) -> Self {
+        Self {
+            bus,
+            start,
+            peripheral,
+        }
+    }
+
+    /// Returns the peripheral
+    pub fn peripheral_mut(&mut self) -> &mut P {
+        &mut self.peripheral
+    }
+
+    /// Returns the underlying bus
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    /// Consumes the MappedBus and returns the bus and peripheral
+    pub fn release(self) -> (B, P) {
+        (self.bus, self.peripheral)
+    }
+
+    fn offset(&self, address: usize) -> Option<usize> {
+        address
+            .checked_sub(self.start)
+            .filter(|offset| *offset < self.peripheral.len())
+    }
+}
+
+impl<B, P> Bus for MappedBus<B, P>
+where
+    B: Bus,
+    P: Bus,
+{
+    fn len(&self) -> usize {
+        self.bus.len()
+    }
+
+    fn read(&mut self, address: usize) -> u8 {
+        match self.offset(address) {
+            Some(offset) => self.peripheral.read(offset),
+            None => self.bus.read(address),
+        }
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        match self.offset(address) {
+            Some(offset) => self.peripheral.write(offset, value),
+            None => self.bus.write(address, value),
+        }
+    }
+
+    fn peek(&self, address: usize) -> Option<u8> {
+        match self.offset(address) {
+            Some(offset) => self.peripheral.peek(offset),
+            None => self.bus.peek(address),
+        }
+    }
+
+    fn faulted(&self) -> bool {
+        self.bus.faulted() || self.peripheral.faulted()
+    }
+}
+
+/// Error recorded by a [`SpiRam`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpiRamError<S, P> {
+    /// The SPI transfer failed
+    Spi(S),
+    /// Driving chip select failed
+    ChipSelect(P),
+}
+
+/// SPI SRAM command to read memory
+const SPI_READ: u8 = 0x03;
+/// SPI SRAM command to write memory
+const SPI_WRITE: u8 = 0x02;
+
+/// External SPI SRAM such as the 23K256, 23LC512 or 23LC1024
+/// used in its default sequential mode
+///
+/// Failed accesses read as 0 and mark the bus as faulted, which halts the
+/// interpreter. The error can then be retrieved with `take_error`.
+pub struct SpiRam<SPI, CS, SE, PE>
+where
+    SPI: Transfer<u8, Error = SE> + Write<u8, Error = SE>,
+    CS: OutputPin<Error = PE>,
+{
+    spi: SPI,
+    cs: CS,
+    size: usize,
+    address_bytes: usize,
+    error: Option<SpiRamError<SE, PE>>,
+}
+
+impl<SPI, CS, SE, PE> SpiRam<SPI, CS, SE, PE>
+where
+    SPI: Transfer<u8, Error = SE> + Write<u8, Error = SE>,
+    CS: OutputPin<Error = PE>,
+{
+    /// Creates a SpiRam of `size` bytes addressed with `address_bytes`
+    /// bytes, 2 for the 23K256 and 3 for the 23LC512 and 23LC1024
+    pub fn new(spi: SPI, mut cs: CS, size: usize, address_bytes: usize) -> Result<Self, PE> {
+        cs.set_high()?;
+        Ok(Self {
+            spi,
+            cs,
+            size,
+            address_bytes,
+            error: None,
+        })
+    }
+
+    /// Returns and clears the recorded error
+    pub fn take_error(&mut self) -> Option<SpiRamError<SE, PE>> {
+        self.error.take()
+    }
+
+    /// Consumes the SpiRam and returns the SPI bus and chip select pin
+    pub fn release(self) -> (SPI, CS) {
+        (self.spi, self.cs)
+    }
+
+    /// Runs a single command with chip select held low
+    fn transaction(&mut self, command: u8, address: usize, data: &mut [u8]) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = self.try_transaction(command, address, data);
+        let released = self.cs.set_high().map_err(SpiRamError::ChipSelect);
+        if let Err(error) = result.and(released) {
+            self.error = Some(error);
+        }
+    }
+
+    fn try_transaction(
+        &mut self,
+        command: u8,
+        address: usize,
+        data: &mut [u8],
+    ) -> Result<(), SpiRamError<SE, PE>> {
+        self.cs.set_low().map_err(SpiRamError::ChipSelect)?;
+        let mut header = [command, 0, 0, 0];
+        for i in 0..self.address_bytes {
+            header[1 + i] = (address >> (8 * (self.address_bytes - 1 - i))) as u8;
+        }
+        self.spi
+            .write(&header[..1 + self.address_bytes])
+            .map_err(SpiRamError::Spi)?;
+        if command == SPI_READ {
+            self.spi.transfer(data).map_err(SpiRamError::Spi)?;
+        } else {
+            self.spi.write(data).map_err(SpiRamError::Spi)?;
+        }
+        Ok(())
+    }
+}
+
+impl<SPI, CS, SE, PE> Bus for SpiRam<SPI, CS, SE, PE>
+where
+    SPI: Transfer<u8, Error = SE> + Write<u8, Error = SE>,
+    CS: OutputPin<Error = PE>,
+{
+    fn len(&self) -> usize {
+        self.size
+    }
+
+    fn read(&mut self, address: usize) -> u8 {
+        let mut buffer = [0];
+        self.read_slice(address, &mut buffer);
+        buffer[0]
+    }
+
+    fn write(&mut self, address: usize, value: u8) {
+        self.write_slice(address, &[value]);
+    }
+
+    fn read_slice(&mut self, address: usize, buffer: &mut [u8]) {
+        buffer.fill(0);
+        self.transaction(SPI_READ, address, buffer);
+        if self.error.is_some() {
+            buffer.fill(0);
+        }
+    }
+
+    fn write_slice(&mut self, address: usize, data: &[u8]) {
+        // Chunked so the bytes can be handed to the SPI bus mutably
+        let mut chunk = [0; 16];
+        for (i, part) in data.chunks(chunk.len()).enumerate() {
+            chunk[..part.len()].copy_from_slice(part);
+            let address = address + i * chunk.len();
+            self.transaction(SPI_WRITE, address, &mut chunk[..part.len()]);
+        }
+    }
+
+    fn faulted(&self) -> bool {
+        self.error.is_some()
+    }
+}
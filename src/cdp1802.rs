@@ -0,0 +1,353 @@
+use crate::extension::{Machine, OpcodeExtension, PcAdvance};
+use crate::{Instruction, Opcode};
+
+/// Where the COSMAC VIP interpreter keeps v0 to vf
+const VIP_REGISTERS: u16 = 0xEF0;
+/// Initial value of the 1802 stack pointer r2 on the COSMAC VIP
+const VIP_STACK: u16 = 0xECF;
+/// High byte of the COSMAC VIP display address, kept in rb
+const VIP_DISPLAY_PAGE: u16 = 0x0F;
+
+/// An RCA CDP1802 CPU for running `0nnn` machine language subroutines
+///
+/// Registered as an [`OpcodeExtension`], a `0nnn` opcode runs the 1802 code
+/// at `nnn` against chip8 memory until it returns to the interpreter with
+/// `D4` (`SEP R4`). The registers are set up the way the COSMAC VIP
+/// interpreter leaves them:
+/// * r2 is the stack pointer starting at 0xECF
+/// * r3 is the program counter
+/// * r5 holds the address of the next chip8 instruction
+/// * ra holds the index register
+/// * rb holds the display page 0x0F
+///
+/// v0 to vf are copied to memory at 0xEF0 before the routine runs and read
+/// back afterwards together with the index register from ra.
+///
+/// Input and output instructions are not connected to anything, `OUT`
+/// discards the byte and `INP` reads 0, and the EF flags are never set.
+/// A routine that does not return within the cycle limit is abandoned and
+/// the `0nnn` is skipped as if no extension were registered.
+pub struct Cdp1802 {
+    r: [u16; 16],
+    p: u8,
+    x: u8,
+    d: u8,
+    df: bool,
+    t: u8,
+    ie: bool,
+    q: bool,
+    registers_address: u16,
+    max_cycles: u32,
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cdp1802 {
+    /// Creates a Cdp1802 using the COSMAC VIP memory layout
+    /// and a limit of 10000 instructions per routine
+    pub const fn new() -> Self {
+        Self {
+            r: [0; 16],
+            p: 0,
+            x: 0,
+            d: 0,
+            df: false,
+            t: 0,
+            ie: true,
+            q: false,
+            registers_address: VIP_REGISTERS,
+            max_cycles: 10_000,
+        }
+    }
+
+    /// Sets where v0 to vf are copied for the routine
+    pub fn set_registers_address(&mut self, address: u16) {
+        self.registers_address = address;
+    }
+
+    /// Sets how many 1802 instructions a routine may execute
+    pub fn set_max_cycles(&mut self, max_cycles: u32) {
+        self.max_cycles = max_cycles;
+    }
+
+    /// Returns the sixteen 16 bit registers r0 to rf
+    pub fn registers(&self) -> [u16; 16] {
+        self.r
+    }
+
+    /// Returns the accumulator D
+    pub fn accumulator(&self) -> u8 {
+        self.d
+    }
+
+    /// Returns the state of the Q output
+    pub fn q(&self) -> bool {
+        self.q
+    }
+
+    /// Runs the routine at `address`, returning false
+    /// if it did not return within the cycle limit
+    fn call(&mut self, address: u16, machine: &mut Machine) -> bool {
+        machine
+            .memory
+            .write_slice(self.registers_address as usize, &machine.registers[..]);
+        self.r[2] = VIP_STACK;
+        self.r[3] = address;
+        self.r[5] = machine.program_counter + 2;
+        self.r[0xa] = *machine.index;
+        self.r[0xb] = VIP_DISPLAY_PAGE << 8;
+        self.p = 3;
+        self.x = 2;
+        let mut returned = false;
+        for _ in 0..self.max_cycles {
+            if !self.step(machine) {
+                returned = true;
+                break;
+            }
+        }
+        machine
+            .memory
+            .read_slice(self.registers_address as usize, &mut machine.registers[..]);
+        *machine.index = self.r[0xa];
+        returned
+    }
+
+    fn read(&self, machine: &mut Machine, address: u16) -> u8 {
+        let len = machine.memory.len();
+        machine.memory.read(address as usize % len)
+    }
+
+    fn write(&self, machine: &mut Machine, address: u16, value: u8) {
+        let len = machine.memory.len();
+        machine.memory.write(address as usize % len, value);
+    }
+
+    /// Reads the byte at the program counter and advances it
+    fn fetch(&mut self, machine: &mut Machine) -> u8 {
+        let pc = self.r[self.p as usize];
+        self.r[self.p as usize] = pc.wrapping_add(1);
+        self.read(machine, pc)
+    }
+
+    /// Adds `a`, `b` and `carry` setting DF on carry out
+    fn add(&mut self, a: u8, b: u8, carry: bool) -> u8 {
+        let sum = a as u16 + b as u16 + carry as u16;
+        self.df = sum > 0xff;
+        sum as u8
+    }
+
+    /// Subtracts `b` from `a` with DF as the inverted borrow
+    fn subtract(&mut self, a: u8, b: u8, borrow: bool) -> u8 {
+        self.add(a, !b, !borrow)
+    }
+
+    /// Executes one instruction, returning false on `SEP R4`
+    fn step(&mut self, machine: &mut Machine) -> bool {
+        let opcode = self.fetch(machine);
+        let (i, n) = (opcode >> 4, (opcode & 0xf) as usize);
+        let rx = self.x as usize;
+        match i {
+            // IDL waits for an interrupt, there are none so carry on
+            0x0 if n == 0 => {}
+            0x0 => self.d = self.read(machine, self.r[n]),
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),
+            0x3 => {
+                let taken = self.condition(n);
+                let pc = self.r[self.p as usize];
+                if taken {
+                    let target = self.read(machine, pc) as u16;
+                    self.r[self.p as usize] = (pc & 0xff00) | target;
+                } else {
+                    self.r[self.p as usize] = pc.wrapping_add(1);
+                }
+            }
+            0x4 => {
+                self.d = self.read(machine, self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => self.write(machine, self.r[n], self.d),
+            0x6 if n == 0 => self.r[rx] = self.r[rx].wrapping_add(1),
+            // OUT, the byte goes nowhere
+            0x6 if n < 8 => self.r[rx] = self.r[rx].wrapping_add(1),
+            // Undefined on the 1802
+            0x6 if n == 8 => {}
+            // INP, nothing is connected
+            0x6 => {
+                self.d = 0;
+                self.write(machine, self.r[rx], 0);
+            }
+            0x7 => self.extended(n, machine),
+            0x8 => self.d = self.r[n] as u8,
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            0xa => self.r[n] = (self.r[n] & 0xff00) | self.d as u16,
+            0xb => self.r[n] = (self.r[n] & 0x00ff) | ((self.d as u16) << 8),
+            0xc => self.long_branch(n, machine),
+            0xd if n == 4 => return false,
+            0xd => self.p = n as u8,
+            0xe => self.x = n as u8,
+            _ => self.arithmetic(n, machine),
+        }
+        true
+    }
+
+    /// The condition tested by short and long branches, before inverting
+    /// for opcodes 8 to F. The EF flags are never set.
+    fn condition(&self, n: usize) -> bool {
+        let condition = match n & 7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            _ => false,
+        };
+        if n < 8 {
+            condition
+        } else {
+            !condition
+        }
+    }
+
+    /// 7N instructions
+    fn extended(&mut self, n: usize, machine: &mut Machine) {
+        let rx = self.x as usize;
+        match n {
+            // RET and DIS
+            0x0 | 0x1 => {
+                let xp = self.read(machine, self.r[rx]);
+                self.r[rx] = self.r[rx].wrapping_add(1);
+                self.x = xp >> 4;
+                self.p = xp & 0xf;
+                self.ie = n == 0;
+            }
+            // LDXA
+            0x2 => {
+                self.d = self.read(machine, self.r[rx]);
+                self.r[rx] = self.r[rx].wrapping_add(1);
+            }
+            // STXD
+            0x3 => {
+                self.write(machine, self.r[rx], self.d);
+                self.r[rx] = self.r[rx].wrapping_sub(1);
+            }
+            // SHRC
+            0x6 => {
+                let carry = self.df;
+                self.df = self.d & 1 != 0;
+                self.d = (self.d >> 1) | ((carry as u8) << 7);
+            }
+            // SAV
+            0x8 => self.write(machine, self.r[rx], self.t),
+            // MARK
+            0x9 => {
+                self.t = (self.x << 4) | self.p;
+                self.write(machine, self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            // REQ and SEQ
+            0xa | 0xb => self.q = n == 0xb,
+            // SHLC
+            0xe => {
+                let carry = self.df;
+                self.df = self.d & 0x80 != 0;
+                self.d = (self.d << 1) | carry as u8;
+            }
+            // ADC, SDB, SMB and their immediate forms
+            _ => {
+                let operand = if n < 8 {
+                    self.read(machine, self.r[rx])
+                } else {
+                    self.fetch(machine)
+                };
+                self.d = match n & 7 {
+                    4 => self.add(self.d, operand, self.df),
+                    5 => self.subtract(operand, self.d, !self.df),
+                    _ => self.subtract(self.d, operand, !self.df),
+                };
+            }
+        }
+    }
+
+    /// CN long branches and skips
+    fn long_branch(&mut self, n: usize, machine: &mut Machine) {
+        let pc = self.r[self.p as usize];
+        match n {
+            // NOP
+            0x4 => {}
+            // LSNQ, LSNZ, LSNF, LSIE, LSQ, LSZ and LSDF skip two bytes
+            0x5..=0x7 | 0xc..=0xf => {
+                let skip = match n {
+                    0x5 => !self.q,
+                    0x6 => self.d != 0,
+                    0x7 => !self.df,
+                    0xc => self.ie,
+                    0xd => self.q,
+                    0xe => self.d == 0,
+                    _ => self.df,
+                };
+                if skip {
+                    self.r[self.p as usize] = pc.wrapping_add(2);
+                }
+            }
+            // LBR, LBQ, LBZ, LBDF, LSKP, LBNQ, LBNZ and LBNF
+            _ => {
+                if self.condition(n) {
+                    let high = self.read(machine, pc) as u16;
+                    let low = self.read(machine, pc.wrapping_add(1)) as u16;
+                    self.r[self.p as usize] = (high << 8) | low;
+                } else {
+                    self.r[self.p as usize] = pc.wrapping_add(2);
+                }
+            }
+        }
+    }
+
+    /// FN memory reference and immediate arithmetic
+    fn arithmetic(&mut self, n: usize, machine: &mut Machine) {
+        match n {
+            // SHR
+            0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            // SHL
+            0xe => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => {
+                let operand = if n < 8 {
+                    let rx = self.x as usize;
+                    self.read(machine, self.r[rx])
+                } else {
+                    self.fetch(machine)
+                };
+                self.d = match n & 7 {
+                    0 => operand,
+                    1 => self.d | operand,
+                    2 => self.d & operand,
+                    3 => self.d ^ operand,
+                    4 => self.add(self.d, operand, false),
+                    5 => self.subtract(operand, self.d, false),
+                    _ => self.subtract(self.d, operand, false),
+                };
+            }
+        }
+    }
+}
+
+impl OpcodeExtension for Cdp1802 {
+    fn execute(&mut self, opcode: Opcode, machine: &mut Machine) -> Option<PcAdvance> {
+        match Instruction::decode(opcode) {
+            Some(Instruction::SysCall(address)) if self.call(address, machine) => {
+                Some(PcAdvance::Next)
+            }
+            _ => None,
+        }
+    }
+}
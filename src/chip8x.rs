@@ -0,0 +1,167 @@
+use core::fmt;
+
+use embedded_graphics::pixelcolor::Rgb565;
+
+use crate::{Nibble, Opcode};
+
+/// Number of color zones across the screen, each 8 pixels wide
+pub const ZONE_COLUMNS: usize = 8;
+/// Number of rows in the color map, one per screen line
+pub const ZONE_ROWS: usize = 32;
+/// Lines covered by a zone set with `bxy0`
+const ZONE_HEIGHT: usize = 4;
+
+/// Foreground colors of the VIP color board
+pub const FOREGROUND_COLORS: [Rgb565; 8] = [
+    Rgb565::new(0, 0, 0),
+    Rgb565::new(31, 0, 0),
+    Rgb565::new(0, 0, 31),
+    Rgb565::new(31, 0, 31),
+    Rgb565::new(0, 63, 0),
+    Rgb565::new(31, 63, 0),
+    Rgb565::new(0, 63, 31),
+    Rgb565::new(31, 63, 31),
+];
+
+/// Background colors of the VIP color board in the order `02a0` cycles them
+pub const BACKGROUND_COLORS: [Rgb565; 4] = [
+    Rgb565::new(0, 0, 15),
+    Rgb565::new(0, 0, 0),
+    Rgb565::new(0, 31, 0),
+    Rgb565::new(15, 0, 0),
+];
+
+/// A CHIP-8X instruction
+///
+/// These replace or add to the chip8 instructions in CHIP-8X mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8XInstruction {
+    /// 02a0 Step to the next background color
+    CycleBackground,
+    /// 5xy1 Add each nibble of vy to vx modulo 8, without carry between them
+    AddNibbles { x: Nibble, y: Nibble },
+    /// bxy0 Set the foreground color of zones to vy, vx holds the column in
+    /// the low nibble and width minus one in the high nibble, vx+1 the zone
+    /// row and height minus one the same way with zones 4 lines high
+    ZoneColor { x: Nibble, y: Nibble },
+    /// bxyn Set the foreground color of n lines to vy, vx holds the zone
+    /// columns as for `bxy0` and vx+1 the first line
+    LineColor { x: Nibble, y: Nibble, n: Nibble },
+    /// exf2 Skip if key vx is held on the second keypad
+    Skp2 { x: Nibble },
+    /// exf5 Skip if key vx is not held on the second keypad
+    Sknp2 { x: Nibble },
+}
+
+impl Chip8XInstruction {
+    /// Decodes an opcode, returning `None` if it is not a CHIP-8X instruction
+    pub fn decode(opcode: Opcode) -> Option<Chip8XInstruction> {
+        use Chip8XInstruction::*;
+        let (x, y, n) = (opcode.0 & 0xf, opcode.1 >> 4, opcode.1 & 0xf);
+        Some(match (opcode.0 >> 4, opcode.1) {
+            (0x0, 0xa0) if x == 2 => CycleBackground,
+            (0x5, _) if n == 1 => AddNibbles { x, y },
+            (0xb, _) if n == 0 => ZoneColor { x, y },
+            (0xb, _) => LineColor { x, y, n },
+            (0xe, 0xf2) => Skp2 { x },
+            (0xe, 0xf5) => Sknp2 { x },
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for Chip8XInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Chip8XInstruction::*;
+        match *self {
+            CycleBackground => write!(f, "BGCOL"),
+            AddNibbles { x, y } => write!(f, "ADDN V{:X}, V{:X}", x, y),
+            ZoneColor { x, y } => write!(f, "COL V{:X}, V{:X}", x, y),
+            LineColor { x, y, n } => write!(f, "COL V{:X}, V{:X}, {}", x, y, n),
+            Skp2 { x } => write!(f, "SKP2 V{:X}", x),
+            Sknp2 { x } => write!(f, "SKNP2 V{:X}", x),
+        }
+    }
+}
+
+/// CHIP-8X color state
+///
+/// The screen is colored in zones 8 pixels wide and one line high, each
+/// with one of the eight [`FOREGROUND_COLORS`] for set pixels, while clear
+/// pixels use one of the four [`BACKGROUND_COLORS`]. The map starts red on
+/// a dark blue background as on the VIP color board.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chip8X {
+    zones: [[u8; ZONE_COLUMNS]; ZONE_ROWS],
+    background: u8,
+}
+
+impl Default for Chip8X {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chip8X {
+    /// Creates the CHIP-8X color state
+    pub const fn new() -> Self {
+        Self {
+            zones: [[1; ZONE_COLUMNS]; ZONE_ROWS],
+            background: 0,
+        }
+    }
+
+    /// Returns the index of the background color
+    pub fn background(&self) -> u8 {
+        self.background
+    }
+
+    /// Returns the index of the foreground color of a zone
+    pub fn foreground(&self, column: usize, row: usize) -> u8 {
+        self.zones[row % ZONE_ROWS][column % ZONE_COLUMNS]
+    }
+
+    /// Returns the color of a pixel on a screen of `size`
+    pub fn color(&self, x: usize, y: usize, on: bool, size: (usize, usize)) -> Rgb565 {
+        match on {
+            true => {
+                let column = x * ZONE_COLUMNS / size.0;
+                let row = y * ZONE_ROWS / size.1;
+                FOREGROUND_COLORS[self.foreground(column, row) as usize]
+            }
+            false => BACKGROUND_COLORS[self.background as usize],
+        }
+    }
+
+    /// Steps to the next background color
+    pub(crate) fn cycle_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUND_COLORS.len() as u8;
+    }
+
+    /// Colors zones for `bxy0` and `bxyn`, `lines` of `None` means
+    /// `bxy0`. Returns the changed columns and lines.
+    pub(crate) fn set_color(
+        &mut self,
+        columns: u8,
+        rows: u8,
+        lines: Option<Nibble>,
+        color: u8,
+    ) -> ((usize, usize), (usize, usize)) {
+        let first_column = (columns & 0xf) as usize;
+        let last_column = (first_column + (columns >> 4) as usize).min(ZONE_COLUMNS - 1);
+        let (first_line, count) = match lines {
+            Some(n) => (rows as usize, n as usize),
+            None => (
+                (rows & 0xf) as usize * ZONE_HEIGHT,
+                ((rows >> 4) as usize + 1) * ZONE_HEIGHT,
+            ),
+        };
+        let last_line = (first_line + count).min(ZONE_ROWS);
+        for row in &mut self.zones[first_line.min(ZONE_ROWS)..last_line] {
+            for zone in &mut row[first_column.min(ZONE_COLUMNS)..=last_column] {
+                *zone = color & 7;
+            }
+        }
+        ((first_column, last_column + 1), (first_line, last_line))
+    }
+}
@@ -0,0 +1,46 @@
+use crate::bus::Bus;
+use crate::Opcode;
+
+/// How the program counter moves after an extension handles an opcode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcAdvance {
+    /// Continue with the next instruction
+    Next,
+    /// Skip the next instruction
+    Skip,
+    /// Continue at the given address
+    Jump(u16),
+}
+
+/// The interpreter state an [`OpcodeExtension`] may read and change
+pub struct Machine<'a> {
+    /// Data registers v0 to vf
+    pub registers: &'a mut [u8; 16],
+    /// The index register
+    pub index: &'a mut u16,
+    /// The interpreter's memory
+    pub memory: &'a mut dyn Bus,
+    /// Address of the opcode being executed
+    pub program_counter: u16,
+}
+
+/// User defined instructions
+///
+/// A registered extension is offered every opcode the interpreter
+/// does not recognise as well as `0nnn` machine language calls, which
+/// the interpreter otherwise ignores. This allows programs to talk to
+/// sensors, LEDs and other hardware of the host.
+pub trait OpcodeExtension {
+    /// Executes an opcode, returning `None` if the extension does not
+    /// handle it, in which case the unknown opcode policy applies
+    fn execute(&mut self, opcode: Opcode, machine: &mut Machine) -> Option<PcAdvance>;
+}
+
+/// The extension used when none is registered, it handles nothing
+pub struct NoExtension;
+
+impl OpcodeExtension for NoExtension {
+    fn execute(&mut self, _opcode: Opcode, _machine: &mut Machine) -> Option<PcAdvance> {
+        None
+    }
+}
@@ -0,0 +1,248 @@
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Dimensions, OriginDimensions, Point, Size},
+    image::ImageDrawable,
+    pixelcolor::Rgb565,
+    prelude::RgbColor,
+    primitives::{PointsIter, Rectangle},
+};
+
+use crate::{CHIP8_HEIGHT, CHIP8_WIDTH, MAX_HEIGHT, MAX_WIDTH};
+
+/// Number of drawing planes, XO-CHIP draws on two
+pub const PLANES: usize = 2;
+
+/// A row of pixels, the leftmost pixel in the most significant bit
+pub type Row = u128;
+
+/// Pixels kept in each word of a framebuffer
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// Words of a framebuffer holding both planes at 128x64
+pub const FULL_SIZE: usize = PLANES * MAX_HEIGHT * MAX_WIDTH / WORD_BITS;
+
+/// Words of a framebuffer holding one plane at 64x32,
+/// enough for programs that only use the standard screen
+pub const CHIP8_SIZE: usize = CHIP8_HEIGHT * CHIP8_WIDTH / WORD_BITS;
+
+/// Colors of pixels with neither plane, the first plane,
+/// the second plane and both planes set
+pub const PLANE_COLORS: [Rgb565; 4] = [
+    Rgb565::BLACK,
+    Rgb565::WHITE,
+    Rgb565::new(21, 42, 21),
+    Rgb565::new(10, 21, 10),
+];
+
+/// Returns the row bits covered by a screen `width` pixels wide
+pub(crate) const fn row_mask(width: usize) -> Row {
+    !0 << (MAX_WIDTH - width)
+}
+
+/// Places the `columns` leftmost bits of `sprite` at `x` in a row
+/// `width` pixels wide, wrapping the part past the right edge
+/// to the left when `wrap` is set and dropping it otherwise
+pub(crate) fn place_sprite(sprite: u16, columns: usize, x: usize, width: usize, wrap: bool) -> Row {
+    let sprite = (sprite as Row) << (MAX_WIDTH - u16::BITS as usize);
+    let sprite = sprite & (!0 << (MAX_WIDTH - columns));
+    let mut row = sprite >> x;
+    if wrap {
+        row |= sprite.checked_shl((width - x) as u32).unwrap_or(0);
+    }
+    row & row_mask(width)
+}
+
+/// The chip8 screen packed one bit per pixel
+///
+/// The pixels are kept in `WORDS` 64 bit words, each line of a plane
+/// taking one word at 64 pixels wide and two at 128, with the lines of
+/// the first plane before those of the second. The default [`FULL_SIZE`]
+/// holds both planes at the largest resolution in 2K, [`CHIP8_SIZE`]
+/// holds one plane of the standard screen in 256 bytes. Lines that do
+/// not fit, those of the second plane first, read as clear and drawing
+/// on them is dropped.
+///
+/// The framebuffer is an [`ImageDrawable`] using the plane colors, so
+/// it can be drawn anywhere with an embedded-graphics `Image`
+/// ```ignore
+/// let framebuffer = *chip8.get_framebuffer();
+/// Image::new(&framebuffer, Point::new(8, 8)).draw(&mut display)?;
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Framebuffer<const WORDS: usize = FULL_SIZE> {
+    words: [u64; WORDS],
+    width: usize,
+    height: usize,
+    colors: [Rgb565; 4],
+}
+
+impl<const WORDS: usize> Framebuffer<WORDS> {
+    /// Creates a clear framebuffer
+    pub(crate) const fn new(width: usize, height: usize) -> Self {
+        Self {
+            words: [0; WORDS],
+            width,
+            height,
+            colors: PLANE_COLORS,
+        }
+    }
+
+    /// Returns the width in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the planes set at (x, y), one bit per plane,
+    /// 0 for points off the screen
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        if x >= self.width || y >= self.height {
+            return 0;
+        }
+        let bit = 1 << (MAX_WIDTH - 1 - x);
+        (0..PLANES).fold(0, |set, plane| match self.row(plane, y) & bit {
+            0 => set,
+            _ => set | (1 << plane),
+        })
+    }
+
+    /// Returns a row of a plane
+    pub fn row(&self, plane: usize, y: usize) -> Row {
+        self.read_row(self.width, self.height, plane, y)
+    }
+
+    /// Returns the rows of a plane from the top of the screen
+    pub fn rows(&self, plane: usize) -> impl Iterator<Item = Row> + '_ {
+        (0..self.height).map(move |y| self.row(plane, y))
+    }
+
+    /// Returns the planes set at each pixel of line `y` from the left
+    pub fn line(&self, y: usize) -> impl Iterator<Item = u8> + '_ {
+        (0..self.width).map(move |x| self.get(x, y))
+    }
+
+    /// Returns the colors of pixels, indexed by the planes set
+    pub fn colors(&self) -> [Rgb565; 4] {
+        self.colors
+    }
+
+    /// Returns the color of the pixel at (x, y)
+    pub fn color(&self, x: usize, y: usize) -> Rgb565 {
+        self.colors[(self.get(x, y) & 3) as usize]
+    }
+
+    pub(crate) fn set_colors(&mut self, colors: [Rgb565; 4]) {
+        self.colors = colors;
+    }
+
+    /// Changes the size, keeping the pixels that are still on screen
+    ///
+    /// Lines are moved in place, starting from the end when they
+    /// move further into the words and from the start otherwise
+    pub(crate) fn resize(&mut self, width: usize, height: usize) {
+        let (old_width, old_height) = (self.width, self.height);
+        if width >= old_width && height >= old_height {
+            for plane in (0..PLANES).rev() {
+                for y in (0..height).rev() {
+                    let row = self.read_row(old_width, old_height, plane, y);
+                    self.write_row(width, height, plane, y, row);
+                }
+            }
+        } else if width <= old_width && height <= old_height {
+            for plane in 0..PLANES {
+                for y in 0..height {
+                    let row = self.read_row(old_width, old_height, plane, y);
+                    self.write_row(width, height, plane, y, row & row_mask(width));
+                }
+            }
+        } else {
+            self.words.fill(0);
+        }
+        self.width = width;
+        self.height = height;
+    }
+
+    /// Clears the planes set in `planes`
+    pub(crate) fn clear(&mut self, planes: u8) {
+        for plane in 0..PLANES {
+            if planes & (1 << plane) != 0 {
+                for y in 0..self.height {
+                    self.set_row(plane, y, 0);
+                }
+            }
+        }
+    }
+
+    /// Replaces a row of a plane
+    pub(crate) fn set_row(&mut self, plane: usize, y: usize, row: Row) {
+        self.write_row(
+            self.width,
+            self.height,
+            plane,
+            y,
+            row & row_mask(self.width),
+        );
+    }
+
+    /// Returns the first word of a line laid out for a screen of
+    /// `width` by `height`, or `None` if the line does not fit
+    fn start(width: usize, height: usize, plane: usize, y: usize) -> Option<usize> {
+        let words = width.div_ceil(WORD_BITS);
+        let start = (plane * height + y) * words;
+        (y < height && start + words <= WORDS).then_some(start)
+    }
+
+    fn read_row(&self, width: usize, height: usize, plane: usize, y: usize) -> Row {
+        let Some(start) = Self::start(width, height, plane, y) else {
+            return 0;
+        };
+        self.words[start..start + width.div_ceil(WORD_BITS)]
+            .iter()
+            .enumerate()
+            .fold(0, |row, (i, &word)| {
+                row | (word as Row) << (MAX_WIDTH - WORD_BITS * (i + 1))
+            })
+    }
+
+    fn write_row(&mut self, width: usize, height: usize, plane: usize, y: usize, row: Row) {
+        let Some(start) = Self::start(width, height, plane, y) else {
+            return;
+        };
+        let words = &mut self.words[start..start + width.div_ceil(WORD_BITS)];
+        for (i, word) in words.iter_mut().enumerate() {
+            *word = (row >> (MAX_WIDTH - WORD_BITS * (i + 1))) as u64;
+        }
+    }
+}
+
+impl<const WORDS: usize> OriginDimensions for Framebuffer<WORDS> {
+    fn size(&self) -> Size {
+        Size::new(self.width as u32, self.height as u32)
+    }
+}
+
+impl<const WORDS: usize> ImageDrawable for Framebuffer<WORDS> {
+    type Color = Rgb565;
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        self.draw_sub_image(target, &self.bounding_box())
+    }
+
+    fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let area = area.intersection(&self.bounding_box());
+        let colors = area
+            .points()
+            .map(|point| self.color(point.x as usize, point.y as usize));
+        target.fill_contiguous(&Rectangle::new(Point::zero(), area.size), colors)
+    }
+}
@@ -0,0 +1,367 @@
+use core::fmt;
+
+use crate::{Nibble, Opcode, OpcodeDecoded};
+
+/// A decoded chip8 instruction
+///
+/// Register operands are the register number `0x0`-`0xF`,
+/// `nn` is a byte immediate and addresses are 12 bits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0nnn Call a machine language routine
+    SysCall(u16),
+    /// 00e0 Clear the screen
+    Cls,
+    /// 00ee Return from a subroutine
+    Ret,
+    /// 00cn Scroll the screen down n lines (SUPER-CHIP)
+    ScrollDown(Nibble),
+    /// 00dn Scroll the screen up n lines (XO-CHIP)
+    ScrollUp(Nibble),
+    /// 00fb Scroll the screen right 4 pixels (SUPER-CHIP)
+    ScrollRight,
+    /// 00fc Scroll the screen left 4 pixels (SUPER-CHIP)
+    ScrollLeft,
+    /// 00fd Stop the interpreter (SUPER-CHIP)
+    Exit,
+    /// 00fe Switch to low resolution (SUPER-CHIP)
+    Low,
+    /// 00ff Switch to high resolution (SUPER-CHIP)
+    High,
+    /// 1nnn Jump to nnn
+    Jp(u16),
+    /// 2nnn Call the subroutine at nnn
+    Call(u16),
+    /// 3xnn Skip if vx equals nn
+    SeByte { x: Nibble, nn: u8 },
+    /// 4xnn Skip if vx does not equal nn
+    SneByte { x: Nibble, nn: u8 },
+    /// 5xy0 Skip if vx equals vy
+    SeReg { x: Nibble, y: Nibble },
+    /// 5xy2 Store vx to vy at index (XO-CHIP)
+    SaveRange { x: Nibble, y: Nibble },
+    /// 5xy3 Load vx to vy from index (XO-CHIP)
+    LoadRange { x: Nibble, y: Nibble },
+    /// 6xnn Set vx to nn
+    LdByte { x: Nibble, nn: u8 },
+    /// 7xnn Add nn to vx
+    AddByte { x: Nibble, nn: u8 },
+    /// 8xy0 Set vx to vy
+    LdReg { x: Nibble, y: Nibble },
+    /// 8xy1 Set vx to vx OR vy
+    Or { x: Nibble, y: Nibble },
+    /// 8xy2 Set vx to vx AND vy
+    And { x: Nibble, y: Nibble },
+    /// 8xy3 Set vx to vx XOR vy
+    Xor { x: Nibble, y: Nibble },
+    /// 8xy4 Add vy to vx, vf is the carry
+    AddReg { x: Nibble, y: Nibble },
+    /// 8xy5 Subtract vy from vx, vf is 1 when there is no borrow
+    Sub { x: Nibble, y: Nibble },
+    /// 8xy6 Shift vx right, vf is the bit shifted out
+    Shr { x: Nibble, y: Nibble },
+    /// 8xy7 Set vx to vy minus vx, vf is 1 when there is no borrow
+    Subn { x: Nibble, y: Nibble },
+    /// 8xye Shift vx left, vf is the bit shifted out
+    Shl { x: Nibble, y: Nibble },
+    /// 9xy0 Skip if vx does not equal vy
+    SneReg { x: Nibble, y: Nibble },
+    /// annn Set index to nnn
+    LdI(u16),
+    /// bnnn Jump to nnn plus v0
+    JpV0(u16),
+    /// cxnn Set vx to a random number AND nn
+    Rnd { x: Nibble, nn: u8 },
+    /// dxyn Draw an n byte sprite at (vx, vy), SUPER-CHIP
+    /// and XO-CHIP draw a 16x16 sprite when n is 0
+    Drw { x: Nibble, y: Nibble, n: Nibble },
+    /// ex9e Skip if the key in vx is held
+    Skp { x: Nibble },
+    /// exa1 Skip if the key in vx is not held
+    Sknp { x: Nibble },
+    /// f000 nnnn Set index to the 16 bit address in the next two bytes (XO-CHIP)
+    LdILong,
+    /// fn01 Select the drawing planes n (XO-CHIP)
+    Plane(Nibble),
+    /// f002 Load the 16 byte audio pattern at index (XO-CHIP)
+    Audio,
+    /// fx07 Set vx to the delay timer
+    LdVxDt { x: Nibble },
+    /// fx0a Wait for a key and store it in vx
+    LdVxK { x: Nibble },
+    /// fx15 Set the delay timer to vx
+    LdDtVx { x: Nibble },
+    /// fx18 Set the sound timer to vx
+    LdStVx { x: Nibble },
+    /// fx1e Add vx to index
+    AddI { x: Nibble },
+    /// fx29 Point index at the small font glyph for vx
+    LdF { x: Nibble },
+    /// fx30 Point index at the large font glyph for vx
+    LdHf { x: Nibble },
+    /// fx3a Set the audio pitch to vx (XO-CHIP)
+    Pitch { x: Nibble },
+    /// fx33 Store the decimal digits of vx at index
+    LdB { x: Nibble },
+    /// fx55 Store v0 to vx at index
+    LdIVx { x: Nibble },
+    /// fx65 Load v0 to vx from index
+    LdVxI { x: Nibble },
+    /// fx75 Save v0 to vx to the flag registers (SUPER-CHIP)
+    SaveFlags { x: Nibble },
+    /// fx85 Load v0 to vx from the flag registers (SUPER-CHIP)
+    LoadFlags { x: Nibble },
+}
+
+/// The instructions an interpreter recognises
+///
+/// Opcodes outside the set are `0nnn` calls when they start with 0
+/// and unknown otherwise
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InstructionSet {
+    /// The original instructions, also accepting `fx30`
+    /// which does not clash with anything
+    Chip8,
+    /// SUPER-CHIP 1.1, adding scrolling, high resolution,
+    /// 16x16 sprites and flag registers
+    SuperChip,
+    /// XO-CHIP, adding to SUPER-CHIP drawing planes, audio
+    /// patterns, register ranges and 16 bit addresses
+    XoChip,
+}
+
+impl InstructionSet {
+    /// Returns true if the instruction is part of the set
+    pub fn supports(&self, instruction: Instruction) -> bool {
+        use Instruction::*;
+        let super_chip = matches!(
+            instruction,
+            ScrollDown(_)
+                | ScrollRight
+                | ScrollLeft
+                | Exit
+                | Low
+                | High
+                | SaveFlags { .. }
+                | LoadFlags { .. }
+        );
+        let xo_chip = matches!(
+            instruction,
+            ScrollUp(_)
+                | SaveRange { .. }
+                | LoadRange { .. }
+                | LdILong
+                | Plane(_)
+                | Audio
+                | Pitch { .. }
+        );
+        match self {
+            InstructionSet::Chip8 => !super_chip && !xo_chip,
+            InstructionSet::SuperChip => !xo_chip,
+            InstructionSet::XoChip => true,
+        }
+    }
+}
+
+/// Splits an opcode into its four nibbles
+pub(crate) fn nibbles(opcode: Opcode) -> OpcodeDecoded {
+    (opcode.0 >> 4, opcode.0 & 0xf, opcode.1 >> 4, opcode.1 & 0xf)
+}
+
+/// Combines the last three
+/// nibbles of an opcode into a u16
+fn nnn(opcode: Opcode) -> u16 {
+    (((opcode.0 & 0xf) as u16) << 8) | opcode.1 as u16
+}
+
+impl Instruction {
+    /// Decodes an opcode, returning `None` when it
+    /// is not exactly a known instruction
+    pub fn decode(opcode: Opcode) -> Option<Instruction> {
+        use Instruction::*;
+        let (op, x, y, n) = nibbles(opcode);
+        let nn = opcode.1;
+        let nnn = nnn(opcode);
+        let instruction = match (op, x, y, n) {
+            (0x0, 0x0, 0xe, 0x0) => Cls,
+            (0x0, 0x0, 0xe, 0xe) => Ret,
+            (0x0, 0x0, 0xc, _) => ScrollDown(n),
+            (0x0, 0x0, 0xd, _) => ScrollUp(n),
+            (0x0, 0x0, 0xf, 0xb) => ScrollRight,
+            (0x0, 0x0, 0xf, 0xc) => ScrollLeft,
+            (0x0, 0x0, 0xf, 0xd) => Exit,
+            (0x0, 0x0, 0xf, 0xe) => Low,
+            (0x0, 0x0, 0xf, 0xf) => High,
+            (0x0, _, _, _) => SysCall(nnn),
+            (0x1, _, _, _) => Jp(nnn),
+            (0x2, _, _, _) => Call(nnn),
+            (0x3, _, _, _) => SeByte { x, nn },
+            (0x4, _, _, _) => SneByte { x, nn },
+            (0x5, _, _, 0x0) => SeReg { x, y },
+            (0x5, _, _, 0x2) => SaveRange { x, y },
+            (0x5, _, _, 0x3) => LoadRange { x, y },
+            (0x6, _, _, _) => LdByte { x, nn },
+            (0x7, _, _, _) => AddByte { x, nn },
+            (0x8, _, _, 0x0) => LdReg { x, y },
+            (0x8, _, _, 0x1) => Or { x, y },
+            (0x8, _, _, 0x2) => And { x, y },
+            (0x8, _, _, 0x3) => Xor { x, y },
+            (0x8, _, _, 0x4) => AddReg { x, y },
+            (0x8, _, _, 0x5) => Sub { x, y },
+            (0x8, _, _, 0x6) => Shr { x, y },
+            (0x8, _, _, 0x7) => Subn { x, y },
+            (0x8, _, _, 0xe) => Shl { x, y },
+            (0x9, _, _, 0x0) => SneReg { x, y },
+            (0xa, _, _, _) => LdI(nnn),
+            (0xb, _, _, _) => JpV0(nnn),
+            (0xc, _, _, _) => Rnd { x, nn },
+            (0xd, _, _, _) => Drw { x, y, n },
+            (0xe, _, 0x9, 0xe) => Skp { x },
+            (0xe, _, 0xa, 0x1) => Sknp { x },
+            (0xf, 0x0, 0x0, 0x0) => LdILong,
+            (0xf, _, 0x0, 0x1) => Plane(x),
+            (0xf, 0x0, 0x0, 0x2) => Audio,
+            (0xf, _, 0x0, 0x7) => LdVxDt { x },
+            (0xf, _, 0x0, 0xa) => LdVxK { x },
+            (0xf, _, 0x1, 0x5) => LdDtVx { x },
+            (0xf, _, 0x1, 0x8) => LdStVx { x },
+            (0xf, _, 0x1, 0xe) => AddI { x },
+            (0xf, _, 0x2, 0x9) => LdF { x },
+            (0xf, _, 0x3, 0x0) => LdHf { x },
+            (0xf, _, 0x3, 0xa) => Pitch { x },
+            (0xf, _, 0x3, 0x3) => LdB { x },
+            (0xf, _, 0x5, 0x5) => LdIVx { x },
+            (0xf, _, 0x6, 0x5) => LdVxI { x },
+            (0xf, _, 0x7, 0x5) => SaveFlags { x },
+            (0xf, _, 0x8, 0x5) => LoadFlags { x },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// Encodes the instruction back into its opcode
+    ///
+    /// Operands are masked to the width of their field
+    pub fn encode(&self) -> Opcode {
+        use Instruction::*;
+        let word = |op: u16, x: Nibble, y: Nibble, n: Nibble| {
+            (op << 12) | ((x as u16 & 0xf) << 8) | ((y as u16 & 0xf) << 4) | (n as u16 & 0xf)
+        };
+        let addr = |op: u16, nnn: u16| (op << 12) | (nnn & 0xfff);
+        let byte = |op: u16, x: Nibble, nn: u8| (op << 12) | ((x as u16 & 0xf) << 8) | nn as u16;
+        let word = match *self {
+            SysCall(nnn) => addr(0x0, nnn),
+            Cls => 0x00e0,
+            Ret => 0x00ee,
+            ScrollDown(n) => word(0x0, 0x0, 0xc, n),
+            ScrollUp(n) => word(0x0, 0x0, 0xd, n),
+            ScrollRight => 0x00fb,
+            ScrollLeft => 0x00fc,
+            Exit => 0x00fd,
+            Low => 0x00fe,
+            High => 0x00ff,
+            Jp(nnn) => addr(0x1, nnn),
+            Call(nnn) => addr(0x2, nnn),
+            SeByte { x, nn } => byte(0x3, x, nn),
+            SneByte { x, nn } => byte(0x4, x, nn),
+            SeReg { x, y } => word(0x5, x, y, 0x0),
+            SaveRange { x, y } => word(0x5, x, y, 0x2),
+            LoadRange { x, y } => word(0x5, x, y, 0x3),
+            LdByte { x, nn } => byte(0x6, x, nn),
+            AddByte { x, nn } => byte(0x7, x, nn),
+            LdReg { x, y } => word(0x8, x, y, 0x0),
+            Or { x, y } => word(0x8, x, y, 0x1),
+            And { x, y } => word(0x8, x, y, 0x2),
+            Xor { x, y } => word(0x8, x, y, 0x3),
+            AddReg { x, y } => word(0x8, x, y, 0x4),
+            Sub { x, y } => word(0x8, x, y, 0x5),
+            Shr { x, y } => word(0x8, x, y, 0x6),
+            Subn { x, y } => word(0x8, x, y, 0x7),
+            Shl { x, y } => word(0x8, x, y, 0xe),
+            SneReg { x, y } => word(0x9, x, y, 0x0),
+            LdI(nnn) => addr(0xa, nnn),
+            JpV0(nnn) => addr(0xb, nnn),
+            Rnd { x, nn } => byte(0xc, x, nn),
+            Drw { x, y, n } => word(0xd, x, y, n),
+            Skp { x } => word(0xe, x, 0x9, 0xe),
+            Sknp { x } => word(0xe, x, 0xa, 0x1),
+            LdILong => 0xf000,
+            Plane(n) => word(0xf, n, 0x0, 0x1),
+            Audio => 0xf002,
+            LdVxDt { x } => word(0xf, x, 0x0, 0x7),
+            LdVxK { x } => word(0xf, x, 0x0, 0xa),
+            LdDtVx { x } => word(0xf, x, 0x1, 0x5),
+            LdStVx { x } => word(0xf, x, 0x1, 0x8),
+            AddI { x } => word(0xf, x, 0x1, 0xe),
+            LdF { x } => word(0xf, x, 0x2, 0x9),
+            LdHf { x } => word(0xf, x, 0x3, 0x0),
+            Pitch { x } => word(0xf, x, 0x3, 0xa),
+            LdB { x } => word(0xf, x, 0x3, 0x3),
+            LdIVx { x } => word(0xf, x, 0x5, 0x5),
+            LdVxI { x } => word(0xf, x, 0x6, 0x5),
+            SaveFlags { x } => word(0xf, x, 0x7, 0x5),
+            LoadFlags { x } => word(0xf, x, 0x8, 0x5),
+        };
+        ((word >> 8) as u8, word as u8)
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Formats the instruction as a mnemonic, ie `LD V1, 0x2A`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+        match *self {
+            SysCall(nnn) => write!(f, "SYS 0x{:03X}", nnn),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Low => write!(f, "LOW"),
+            High => write!(f, "HIGH"),
+            Jp(nnn) => write!(f, "JP 0x{:03X}", nnn),
+            Call(nnn) => write!(f, "CALL 0x{:03X}", nnn),
+            SeByte { x, nn } => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            SneByte { x, nn } => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            SeReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            SaveRange { x, y } => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            LoadRange { x, y } => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            LdByte { x, nn } => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            AddByte { x, nn } => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            LdReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Subn { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SneReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(nnn) => write!(f, "LD I, 0x{:03X}", nnn),
+            JpV0(nnn) => write!(f, "JP V0, 0x{:03X}", nnn),
+            Rnd { x, nn } => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Drw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Skp { x } => write!(f, "SKP V{:X}", x),
+            Sknp { x } => write!(f, "SKNP V{:X}", x),
+            LdILong => write!(f, "LD I, LONG"),
+            Plane(n) => write!(f, "PLANE {}", n),
+            Audio => write!(f, "AUDIO"),
+            LdVxDt { x } => write!(f, "LD V{:X}, DT", x),
+            LdVxK { x } => write!(f, "LD V{:X}, K", x),
+            LdDtVx { x } => write!(f, "LD DT, V{:X}", x),
+            LdStVx { x } => write!(f, "LD ST, V{:X}", x),
+            AddI { x } => write!(f, "ADD I, V{:X}", x),
+            LdF { x } => write!(f, "LD F, V{:X}", x),
+            LdHf { x } => write!(f, "LD HF, V{:X}", x),
+            Pitch { x } => write!(f, "PITCH V{:X}", x),
+            LdB { x } => write!(f, "LD B, V{:X}", x),
+            LdIVx { x } => write!(f, "LD [I], V{:X}", x),
+            LdVxI { x } => write!(f, "LD V{:X}, [I]", x),
+            SaveFlags { x } => write!(f, "LD R, V{:X}", x),
+            LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
@@ -1,58 +1,268 @@
+mod expander;
+mod gamepad;
+mod hid;
+mod joystick;
+mod ps2;
+mod shift_register;
+mod touch;
+
+use core::convert::Infallible;
+use core::sync::atomic::{AtomicU16, Ordering};
 use embedded_hal::blocking::delay::DelayMs;
 use embedded_hal::digital::v2::{InputPin, OutputPin};
 
-const R: usize = 4;
-const C: usize = 4;
+pub use expander::{Expander, ExpanderKeyPad};
+pub use gamepad::{Button, ButtonMap, Controller, GamePad};
+pub use hid::{HidKeyboard, InvalidReport, HID_LAYOUT};
+pub use joystick::{AxisCalibration, Joystick, JoystickConfig, JoystickError, NoFire};
+pub use ps2::{Ps2Keyboard, PS2_LAYOUT};
+pub use shift_register::ShiftRegisterKeyPad;
+pub use touch::{TouchController, TouchKeyPad, VirtualKeyPad};
+
+/// A source of chip8 key presses
+///
+/// Implemented by every keypad driver in this module so any of them
+/// can be handed to [`Chip8`](crate::Chip8)
+pub trait KeySource {
+    /// Error returned when the keys cannot be read
+    type Error;
+
+    /// Returns the state of all 16 chip8 keys,
+    /// bit n is set while key n is held down
+    fn scan<D>(&mut self, delay: &mut D) -> Result<u16, Self::Error>
+    where
+        D: DelayMs<u32>;
+}
+
+/// Key state shared between an interrupt handler and the interpreter
+///
+/// Drivers that decode input in an ISR, such as [`Ps2Keyboard`], can
+/// store their key state here and a `&'static SharedKeys` can then be
+/// handed to [`Chip8`](crate::Chip8) as its [`KeySource`]
+pub struct SharedKeys {
+    keys: AtomicU16,
+}
+
+impl SharedKeys {
+    /// Creates a SharedKeys with no keys held
+    pub const fn new() -> Self {
+        Self {
+            keys: AtomicU16::new(0),
+        }
+    }
+
+    /// Replaces the held keys
+    pub fn store(&self, keys: u16) {
+        self.keys.store(keys, Ordering::Relaxed);
+    }
+
+    /// Returns the held keys
+    pub fn load(&self) -> u16 {
+        self.keys.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for SharedKeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeySource for &SharedKeys {
+    type Error = Infallible;
+
+    fn scan<D>(&mut self, _delay: &mut D) -> Result<u16, Infallible>
+    where
+        D: DelayMs<u32>,
+    {
+        Ok(self.load())
+    }
+}
+
+/// The electrical polarity of a button matrix
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    /// Rows idle high and are driven low to be scanned,
+    /// a pressed key reads low
+    ActiveLow,
+    /// Rows idle low and are driven high to be scanned,
+    /// a pressed key reads high
+    ActiveHigh,
+}
+
+/// Scan timing and wiring options for a [`KeyPad`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyPadConfig {
+    /// Wiring of the matrix
+    pub polarity: Polarity,
+    /// Milliseconds to wait after selecting a row before reading the columns
+    pub settle_ms: u32,
+    /// Milliseconds to wait between debounce samples
+    pub debounce_ms: u32,
+    /// Number of additional samples that must all read pressed
+    /// before a key is reported, 0 disables debouncing
+    pub debounce_samples: u8,
+}
+
+impl Default for KeyPadConfig {
+    /// Active low wiring with a 10ms settle time and a single 10ms debounce sample
+    fn default() -> Self {
+        Self {
+            polarity: Polarity::ActiveLow,
+            settle_ms: 10,
+            debounce_ms: 10,
+            debounce_samples: 1,
+        }
+    }
+}
 
 /// A KeyPad struct for representing and scanning a button matrix
 ///
-/// Expects an array of 4 OutputPins and an array of 4 Input Pins
-pub struct KeyPad<O, I>
+/// Expects an array of `ROWS` OutputPins and an array of `COLS` InputPins,
+/// by default a 4x4 matrix. Keys are numbered row by row, so the key at
+/// `(row, col)` has the index `row * COLS + col`.
+pub struct KeyPad<O, I, const ROWS: usize = 4, const COLS: usize = 4>
 where
     O: OutputPin,
     I: InputPin,
 {
-    rows: [O; R],
-    cols: [I; C],
+    rows: [O; ROWS],
+    cols: [I; COLS],
+    config: KeyPadConfig,
 }
 
-impl<O, I> KeyPad<O, I>
+impl<O, I, E, const ROWS: usize, const COLS: usize> KeyPad<O, I, ROWS, COLS>
 where
-    O: OutputPin,
-    I: InputPin,
+    O: OutputPin<Error = E>,
+    I: InputPin<Error = E>,
 {
-    pub fn new<E>(rows: [O; R], cols: [I; C]) -> Self
-    where
-        O: OutputPin<Error = E>,
-        I: InputPin<Error = E>,
-    {
-        let mut s = Self { rows, cols };
-        for r in s.rows.iter_mut() {
-            if r.set_high().is_err() {}
+    /// Creates an active low KeyPad with the default timing
+    pub fn new(rows: [O; ROWS], cols: [I; COLS]) -> Result<Self, E> {
+        Self::with_config(rows, cols, KeyPadConfig::default())
+    }
+
+    /// Creates a KeyPad with the given wiring and timing
+    ///
+    /// All rows are driven to their idle level
+    pub fn with_config(rows: [O; ROWS], cols: [I; COLS], config: KeyPadConfig) -> Result<Self, E> {
+        let mut s = Self { rows, cols, config };
+        for r in 0..ROWS {
+            s.release_row(r)?;
+        }
+        Ok(s)
+    }
+
+    /// Returns the scan configuration
+    pub fn config(&self) -> KeyPadConfig {
+        self.config
+    }
+
+    /// Replaces the scan configuration
+    ///
+    /// All rows are driven to the idle level of the new polarity
+    pub fn set_config(&mut self, config: KeyPadConfig) -> Result<(), E> {
+        self.config = config;
+        for r in 0..ROWS {
+            self.release_row(r)?;
         }
-        s
+        Ok(())
     }
 
-    pub fn get<D>(&mut self, delay: &mut D) -> (bool, u8)
+    /// Scans the matrix and returns the last pressed key found
+    ///
+    /// The bool is true when a key is pressed and the u8
+    /// is the index of that key
+    pub fn get<D>(&mut self, delay: &mut D) -> Result<(bool, u8), E>
     where
         D: DelayMs<u32>,
     {
-        let mut index: u8 = 0;
         let mut key: (bool, u8) = (false, 0);
-        for row in (&mut self.rows).iter_mut() {
-            if row.set_low().is_err() {}
-            delay.delay_ms(10);
-            for col in (&mut self.cols).iter_mut() {
-                if let Ok(_) = col.is_low() {
-                    delay.delay_ms(10);
-                    if col.is_low().is_ok() {
-                        key = (true, index);
-                    }
+        self.scan_matrix(delay, |index| key = (true, index as u8))?;
+        Ok(key)
+    }
+
+    /// Consumes the KeyPad and returns its pins
+    pub fn release(self) -> ([O; ROWS], [I; COLS]) {
+        (self.rows, self.cols)
+    }
+
+    /// Selects each row in turn and calls `pressed`
+    /// with the index of every key found held down
+    fn scan_matrix<D, F>(&mut self, delay: &mut D, mut pressed: F) -> Result<(), E>
+    where
+        D: DelayMs<u32>,
+        F: FnMut(usize),
+    {
+        for r in 0..ROWS {
+            self.select_row(r)?;
+            delay.delay_ms(self.config.settle_ms);
+            for c in 0..COLS {
+                if self.debounced(c, delay)? {
+                    pressed(r * COLS + c);
                 }
-                index += 1;
             }
-            if row.set_high().is_err() {}
+            self.release_row(r)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a column of the selected row through the debounce filter
+    fn debounced<D>(&mut self, col: usize, delay: &mut D) -> Result<bool, E>
+    where
+        D: DelayMs<u32>,
+    {
+        if !self.is_pressed(col)? {
+            return Ok(false);
+        }
+        for _ in 0..self.config.debounce_samples {
+            delay.delay_ms(self.config.debounce_ms);
+            if !self.is_pressed(col)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn is_pressed(&self, col: usize) -> Result<bool, E> {
+        match self.config.polarity {
+            Polarity::ActiveLow => self.cols[col].is_low(),
+            Polarity::ActiveHigh => self.cols[col].is_high(),
+        }
+    }
+
+    fn select_row(&mut self, row: usize) -> Result<(), E> {
+        match self.config.polarity {
+            Polarity::ActiveLow => self.rows[row].set_low(),
+            Polarity::ActiveHigh => self.rows[row].set_high(),
+        }
+    }
+
+    fn release_row(&mut self, row: usize) -> Result<(), E> {
+        match self.config.polarity {
+            Polarity::ActiveLow => self.rows[row].set_high(),
+            Polarity::ActiveHigh => self.rows[row].set_low(),
         }
-        key
+    }
+}
+
+impl<O, I, E, const ROWS: usize, const COLS: usize> KeySource for KeyPad<O, I, ROWS, COLS>
+where
+    O: OutputPin<Error = E>,
+    I: InputPin<Error = E>,
+{
+    type Error = E;
+
+    /// Scans the whole matrix, keys with an index above 0xF are ignored
+    fn scan<D>(&mut self, delay: &mut D) -> Result<u16, E>
+    where
+        D: DelayMs<u32>,
+    {
+        let mut keys: u16 = 0;
+        self.scan_matrix(delay, |index| {
+            if index < 16 {
+                keys |= 1 << index;
+            }
+        })?;
+        Ok(keys)
     }
 }
@@ -0,0 +1,112 @@
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+use super::KeySource;
+
+/// MCP23017 IODIRA register
+const MCP_IODIRA: u8 = 0x00;
+/// MCP23017 GPPUA register
+const MCP_GPPUA: u8 = 0x0c;
+/// MCP23017 GPIOA register
+const MCP_GPIOA: u8 = 0x12;
+/// MCP23017 OLATA register
+const MCP_OLATA: u8 = 0x14;
+
+/// Row pins are the low nibble and column pins the high nibble of the port
+const COLUMN_MASK: u8 = 0xf0;
+
+/// The I2C port expanders supported by [`ExpanderKeyPad`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expander {
+    /// PCF8574 or PCF8574A quasi-bidirectional 8 bit expander
+    Pcf8574,
+    /// MCP23017 16 bit expander, only port A is used
+    Mcp23017,
+}
+
+/// A 4x4 button matrix scanned through an I2C port expander
+///
+/// Rows are wired to pins 0-3 and columns to pins 4-7 of the port.
+/// Rows are driven low one at a time and a pressed key pulls its
+/// column low through the expander's pull-ups.
+pub struct ExpanderKeyPad<I2C> {
+    i2c: I2C,
+    expander: Expander,
+    address: u8,
+    settle_ms: u32,
+}
+
+impl<I2C, E> ExpanderKeyPad<I2C>
+where
+    I2C: Write<Error = E> + Read<Error = E> + WriteRead<Error = E>,
+{
+    /// Creates an ExpanderKeyPad for the expander at the 7 bit `address`
+    ///
+    /// The column pins are configured as inputs and all rows are released
+    pub fn new(i2c: I2C, expander: Expander, address: u8) -> Result<Self, E> {
+        let mut s = Self {
+            i2c,
+            expander,
+            address,
+            settle_ms: 1,
+        };
+        if s.expander == Expander::Mcp23017 {
+            s.i2c.write(s.address, &[MCP_IODIRA, COLUMN_MASK])?;
+            s.i2c.write(s.address, &[MCP_GPPUA, COLUMN_MASK])?;
+        }
+        s.write_port(0xff)?;
+        Ok(s)
+    }
+
+    /// Sets the milliseconds to wait after selecting a row,
+    /// the default is 1
+    pub fn set_settle_ms(&mut self, settle_ms: u32) {
+        self.settle_ms = settle_ms;
+    }
+
+    /// Consumes the ExpanderKeyPad and returns the I2C bus
+    pub fn release(self) -> I2C {
+        self.i2c
+    }
+
+    fn write_port(&mut self, value: u8) -> Result<(), E> {
+        match self.expander {
+            Expander::Pcf8574 => self.i2c.write(self.address, &[value]),
+            Expander::Mcp23017 => self.i2c.write(self.address, &[MCP_OLATA, value]),
+        }
+    }
+
+    fn read_port(&mut self) -> Result<u8, E> {
+        let mut buffer = [0];
+        match self.expander {
+            Expander::Pcf8574 => self.i2c.read(self.address, &mut buffer)?,
+            Expander::Mcp23017 => self
+                .i2c
+                .write_read(self.address, &[MCP_GPIOA], &mut buffer)?,
+        }
+        Ok(buffer[0])
+    }
+}
+
+impl<I2C, E> KeySource for ExpanderKeyPad<I2C>
+where
+    I2C: Write<Error = E> + Read<Error = E> + WriteRead<Error = E>,
+{
+    type Error = E;
+
+    fn scan<D>(&mut self, delay: &mut D) -> Result<u16, E>
+    where
+        D: DelayMs<u32>,
+    {
+        let mut keys: u16 = 0;
+        for row in 0..4 {
+            // Column pins stay high so the PCF8574 can read them
+            self.write_port(!(1 << row))?;
+            delay.delay_ms(self.settle_ms);
+            let columns = !self.read_port()? >> 4;
+            keys |= (columns as u16) << (row * 4);
+        }
+        self.write_port(0xff)?;
+        Ok(keys)
+    }
+}
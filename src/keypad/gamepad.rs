@@ -0,0 +1,227 @@
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+use super::KeySource;
+
+/// Order the NES controller shifts its buttons out in
+const NES_ORDER: [Button; 8] = [
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+    Button::Up,
+    Button::Down,
+    Button::Left,
+    Button::Right,
+];
+
+/// Order the SNES controller shifts its buttons out in,
+/// the last four bits it sends are unused
+const SNES_ORDER: [Button; 12] = [
+    Button::B,
+    Button::Y,
+    Button::Select,
+    Button::Start,
+    Button::Up,
+    Button::Down,
+    Button::Left,
+    Button::Right,
+    Button::A,
+    Button::X,
+    Button::L,
+    Button::R,
+];
+
+/// The type of controller attached to a [`GamePad`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Controller {
+    /// 8 button NES controller
+    Nes,
+    /// 12 button SNES controller
+    Snes,
+}
+
+impl Controller {
+    /// Number of bits clocked out of the controller
+    fn bits(self) -> usize {
+        match self {
+            Controller::Nes => 8,
+            Controller::Snes => 16,
+        }
+    }
+
+    /// The buttons in the order they are clocked out
+    fn order(self) -> &'static [Button] {
+        match self {
+            Controller::Nes => &NES_ORDER,
+            Controller::Snes => &SNES_ORDER,
+        }
+    }
+}
+
+/// A button on an NES or SNES controller
+///
+/// `X`, `Y`, `L` and `R` only exist on the SNES controller
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+    X,
+    Y,
+    L,
+    R,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Maps controller buttons to chip8 keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonMap {
+    keys: [Option<u8>; 12],
+}
+
+impl ButtonMap {
+    /// Creates a mapping with no buttons assigned
+    pub const fn empty() -> Self {
+        Self { keys: [None; 12] }
+    }
+
+    /// Returns the chip8 key a button is mapped to
+    pub fn get(&self, button: Button) -> Option<u8> {
+        self.keys[button as usize]
+    }
+
+    /// Maps a button to a chip8 key, or unmaps it with `None`
+    pub fn set(&mut self, button: Button, key: Option<u8>) {
+        self.keys[button as usize] = key.map(|k| k & 0xf);
+    }
+}
+
+impl Default for ButtonMap {
+    /// The D-pad is mapped to 2/4/6/8, A to 5, B to 0,
+    /// X to B, Y to A, L to 1 and R to 3
+    fn default() -> Self {
+        let mut map = Self::empty();
+        map.set(Button::Up, Some(0x2));
+        map.set(Button::Left, Some(0x4));
+        map.set(Button::Right, Some(0x6));
+        map.set(Button::Down, Some(0x8));
+        map.set(Button::A, Some(0x5));
+        map.set(Button::B, Some(0x0));
+        map.set(Button::X, Some(0xb));
+        map.set(Button::Y, Some(0xa));
+        map.set(Button::L, Some(0x1));
+        map.set(Button::R, Some(0x3));
+        map
+    }
+}
+
+/// An NES or SNES controller read through its latch, clock and data lines
+///
+/// The controller's buttons are active low and clocked out
+/// one bit per clock pulse after the latch is pulsed high.
+pub struct GamePad<L, C, DI>
+where
+    L: OutputPin,
+    C: OutputPin,
+    DI: InputPin,
+{
+    latch: L,
+    clock: C,
+    data: DI,
+    controller: Controller,
+    mapping: ButtonMap,
+}
+
+impl<L, C, DI, E> GamePad<L, C, DI>
+where
+    L: OutputPin<Error = E>,
+    C: OutputPin<Error = E>,
+    DI: InputPin<Error = E>,
+{
+    /// Creates a GamePad with the default [`ButtonMap`]
+    pub fn new(latch: L, clock: C, data: DI, controller: Controller) -> Result<Self, E> {
+        Self::with_mapping(latch, clock, data, controller, ButtonMap::default())
+    }
+
+    /// Creates a GamePad with a custom [`ButtonMap`]
+    ///
+    /// The latch and clock are left low
+    pub fn with_mapping(
+        mut latch: L,
+        mut clock: C,
+        data: DI,
+        controller: Controller,
+        mapping: ButtonMap,
+    ) -> Result<Self, E> {
+        latch.set_low()?;
+        clock.set_low()?;
+        Ok(Self {
+            latch,
+            clock,
+            data,
+            controller,
+            mapping,
+        })
+    }
+
+    /// Returns the button mapping
+    pub fn mapping(&self) -> ButtonMap {
+        self.mapping
+    }
+
+    /// Replaces the button mapping
+    pub fn set_mapping(&mut self, mapping: ButtonMap) {
+        self.mapping = mapping;
+    }
+
+    /// Reads the controller and returns the raw button bits,
+    /// bit n is set when the nth button clocked out is held down
+    pub fn buttons(&mut self) -> Result<u16, E> {
+        self.latch.set_high()?;
+        self.latch.set_low()?;
+        let mut buttons: u16 = 0;
+        for i in 0..self.controller.bits() {
+            if self.data.is_low()? {
+                buttons |= 1 << i;
+            }
+            self.clock.set_high()?;
+            self.clock.set_low()?;
+        }
+        Ok(buttons)
+    }
+
+    /// Consumes the GamePad and returns its pins
+    pub fn release(self) -> (L, C, DI) {
+        (self.latch, self.clock, self.data)
+    }
+}
+
+impl<L, C, DI, E> KeySource for GamePad<L, C, DI>
+where
+    L: OutputPin<Error = E>,
+    C: OutputPin<Error = E>,
+    DI: InputPin<Error = E>,
+{
+    type Error = E;
+
+    fn scan<D>(&mut self, _delay: &mut D) -> Result<u16, E>
+    where
+        D: DelayMs<u32>,
+    {
+        let buttons = self.buttons()?;
+        let mut keys: u16 = 0;
+        for (i, button) in self.controller.order().iter().enumerate() {
+            if buttons & (1 << i) != 0 {
+                if let Some(key) = self.mapping.get(*button) {
+                    keys |= 1 << key;
+                }
+            }
+        }
+        Ok(keys)
+    }
+}
@@ -0,0 +1,106 @@
+use core::convert::Infallible;
+use embedded_hal::blocking::delay::DelayMs;
+
+use super::KeySource;
+
+/// Length of a boot protocol keyboard report
+const REPORT_LEN: usize = 8;
+/// Usage reported in every key slot when too many keys are held
+const ERROR_ROLL_OVER: u8 = 0x01;
+
+/// HID usage IDs for chip8 keys 0x0-0xF on the classic layout
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// Q W E R  ->  4 5 6 D
+/// A S D F      7 8 9 E
+/// Z X C V      A 0 B F
+/// ```
+pub const HID_LAYOUT: [u8; 16] = [
+    0x1b, // X
+    0x1e, // 1
+    0x1f, // 2
+    0x20, // 3
+    0x14, // Q
+    0x1a, // W
+    0x08, // E
+    0x04, // A
+    0x16, // S
+    0x07, // D
+    0x1d, // Z
+    0x06, // C
+    0x21, // 4
+    0x15, // R
+    0x09, // F
+    0x19, // V
+];
+
+/// Error returned for a report that is not a boot protocol keyboard report
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidReport;
+
+/// Turns USB HID boot protocol keyboard reports into chip8 key state
+///
+/// Feed every 8 byte report received from the keyboard to
+/// [`feed`](Self::feed). Reports signalling a rollover error, sent when
+/// more keys are held than the report can describe, leave the previous
+/// key state untouched.
+pub struct HidKeyboard {
+    layout: [u8; 16],
+    keys: u16,
+}
+
+impl HidKeyboard {
+    /// Creates a HidKeyboard using [`HID_LAYOUT`]
+    pub fn new() -> Self {
+        Self::with_layout(HID_LAYOUT)
+    }
+
+    /// Creates a HidKeyboard with a custom layout,
+    /// `layout[n]` is the usage ID of chip8 key n
+    pub fn with_layout(layout: [u8; 16]) -> Self {
+        Self { layout, keys: 0 }
+    }
+
+    /// Updates the key state from a boot protocol report
+    /// and returns the held keys
+    pub fn feed(&mut self, report: &[u8]) -> Result<u16, InvalidReport> {
+        if report.len() != REPORT_LEN {
+            return Err(InvalidReport);
+        }
+        let usages = &report[2..];
+        if usages.contains(&ERROR_ROLL_OVER) {
+            return Ok(self.keys);
+        }
+        let mut keys: u16 = 0;
+        for usage in usages.iter().filter(|&&u| u != 0) {
+            if let Some(key) = self.layout.iter().position(|u| u == usage) {
+                keys |= 1 << key;
+            }
+        }
+        self.keys = keys;
+        Ok(keys)
+    }
+
+    /// Returns the held keys, bit n is set while key n is held down
+    pub fn keys(&self) -> u16 {
+        self.keys
+    }
+}
+
+impl Default for HidKeyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeySource for HidKeyboard {
+    type Error = Infallible;
+
+    fn scan<D>(&mut self, _delay: &mut D) -> Result<u16, Infallible>
+    where
+        D: DelayMs<u32>,
+    {
+        Ok(self.keys)
+    }
+}
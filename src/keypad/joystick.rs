@@ -0,0 +1,252 @@
+use core::convert::Infallible;
+use core::marker::PhantomData;
+use embedded_hal::adc::{Channel, OneShot};
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::digital::v2::InputPin;
+
+use super::{Button, ButtonMap, KeySource};
+
+/// Full scale of a calibrated axis deflection
+const FULL_SCALE: i32 = 1000;
+
+const UP: u8 = 1 << 0;
+const DOWN: u8 = 1 << 1;
+const LEFT: u8 = 1 << 2;
+const RIGHT: u8 = 1 << 3;
+
+/// Raw ADC readings describing the travel of one axis
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AxisCalibration {
+    /// Reading at full deflection left or up
+    pub min: u16,
+    /// Reading with the stick at rest
+    pub center: u16,
+    /// Reading at full deflection right or down, which may be
+    /// below `min` on an axis wired the other way round
+    pub max: u16,
+    /// Swaps the directions of the axis
+    pub inverted: bool,
+}
+
+impl AxisCalibration {
+    /// Calibration for an ADC with the given resolution in bits,
+    /// centered at half scale
+    pub const fn full_range(bits: u8) -> Self {
+        let max = ((1u32 << bits) - 1) as u16;
+        Self {
+            min: 0,
+            center: max / 2,
+            max,
+            inverted: false,
+        }
+    }
+
+    /// Converts a reading to a deflection between -1000 and 1000
+    fn deflection(&self, raw: u16) -> i32 {
+        let (low, high) = (self.min.min(self.max), self.min.max(self.max));
+        let raw = raw.clamp(low, high) as i32;
+        let center = self.center.clamp(low, high) as i32;
+        let deflection = if raw >= center {
+            (raw - center) * FULL_SCALE / (high as i32 - center).max(1)
+        } else {
+            (raw - center) * FULL_SCALE / (center - low as i32).max(1)
+        };
+        if self.inverted != (self.min > self.max) {
+            -deflection
+        } else {
+            deflection
+        }
+    }
+}
+
+/// Calibration and thresholds for a [`Joystick`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JoystickConfig {
+    /// Calibration of the horizontal axis
+    pub x: AxisCalibration,
+    /// Calibration of the vertical axis
+    pub y: AxisCalibration,
+    /// Deflection out of 1000 a direction must exceed to be pressed
+    pub dead_zone: u16,
+    /// How far out of 1000 the deflection must fall back below
+    /// the dead zone before a direction is released
+    pub hysteresis: u16,
+}
+
+impl Default for JoystickConfig {
+    /// A 12 bit ADC with a 30% dead zone and 5% hysteresis
+    fn default() -> Self {
+        Self {
+            x: AxisCalibration::full_range(12),
+            y: AxisCalibration::full_range(12),
+            dead_zone: 300,
+            hysteresis: 50,
+        }
+    }
+}
+
+/// Error returned by a [`Joystick`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoystickError<A, P> {
+    /// Sampling an axis failed
+    Adc(A),
+    /// Reading the fire button failed
+    Fire(P),
+}
+
+/// Placeholder for a [`Joystick`] without a fire button
+pub struct NoFire;
+
+impl InputPin for NoFire {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        Ok(true)
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        Ok(false)
+    }
+}
+
+/// An analog stick sampled with an ADC and mapped to chip8 keys
+///
+/// The directions use the `Up`, `Down`, `Left` and `Right` entries of
+/// the [`ButtonMap`] and the optional active low fire button uses `A`.
+pub struct Joystick<A, ADC, X, Y, F = NoFire>
+where
+    X: Channel<ADC>,
+    Y: Channel<ADC>,
+    F: InputPin,
+{
+    adc: A,
+    x: X,
+    y: Y,
+    fire: F,
+    config: JoystickConfig,
+    mapping: ButtonMap,
+    directions: u8,
+    _adc: PhantomData<ADC>,
+}
+
+impl<A, ADC, X, Y, E> Joystick<A, ADC, X, Y, NoFire>
+where
+    A: OneShot<ADC, u16, X, Error = E> + OneShot<ADC, u16, Y, Error = E>,
+    X: Channel<ADC>,
+    Y: Channel<ADC>,
+{
+    /// Creates a Joystick without a fire button using the default [`ButtonMap`]
+    pub fn new(adc: A, x: X, y: Y, config: JoystickConfig) -> Self {
+        Self {
+            adc,
+            x,
+            y,
+            fire: NoFire,
+            config,
+            mapping: ButtonMap::default(),
+            directions: 0,
+            _adc: PhantomData,
+        }
+    }
+
+    /// Adds an active low fire button
+    pub fn with_fire<F: InputPin>(self, fire: F) -> Joystick<A, ADC, X, Y, F> {
+        Joystick {
+            adc: self.adc,
+            x: self.x,
+            y: self.y,
+            fire,
+            config: self.config,
+            mapping: self.mapping,
+            directions: self.directions,
+            _adc: self._adc,
+        }
+    }
+}
+
+impl<A, ADC, X, Y, F, E> Joystick<A, ADC, X, Y, F>
+where
+    A: OneShot<ADC, u16, X, Error = E> + OneShot<ADC, u16, Y, Error = E>,
+    X: Channel<ADC>,
+    Y: Channel<ADC>,
+    F: InputPin,
+{
+    /// Returns the calibration and thresholds
+    pub fn config(&self) -> JoystickConfig {
+        self.config
+    }
+
+    /// Replaces the calibration and thresholds
+    pub fn set_config(&mut self, config: JoystickConfig) {
+        self.config = config;
+    }
+
+    /// Returns the direction mapping
+    pub fn mapping(&self) -> ButtonMap {
+        self.mapping
+    }
+
+    /// Replaces the direction mapping
+    pub fn set_mapping(&mut self, mapping: ButtonMap) {
+        self.mapping = mapping;
+    }
+
+    /// Samples both axes and returns their deflection
+    /// between -1000 and 1000
+    pub fn read(&mut self) -> Result<(i32, i32), E> {
+        let x: u16 = nb::block!(self.adc.read(&mut self.x))?;
+        let y: u16 = nb::block!(self.adc.read(&mut self.y))?;
+        Ok((self.config.x.deflection(x), self.config.y.deflection(y)))
+    }
+
+    /// Consumes the Joystick and returns the ADC and its pins
+    pub fn release(self) -> (A, X, Y, F) {
+        (self.adc, self.x, self.y, self.fire)
+    }
+
+    /// Presses or releases a direction with hysteresis
+    fn update(&mut self, direction: u8, deflection: i32) {
+        let threshold = self.config.dead_zone as i32;
+        if deflection > threshold {
+            self.directions |= direction;
+        } else if deflection < threshold - self.config.hysteresis as i32 {
+            self.directions &= !direction;
+        }
+    }
+}
+
+impl<A, ADC, X, Y, F, E> KeySource for Joystick<A, ADC, X, Y, F>
+where
+    A: OneShot<ADC, u16, X, Error = E> + OneShot<ADC, u16, Y, Error = E>,
+    X: Channel<ADC>,
+    Y: Channel<ADC>,
+    F: InputPin,
+{
+    type Error = JoystickError<E, F::Error>;
+
+    fn scan<D>(&mut self, _delay: &mut D) -> Result<u16, Self::Error>
+    where
+        D: DelayMs<u32>,
+    {
+        let (x, y) = self.read().map_err(JoystickError::Adc)?;
+        self.update(LEFT, -x);
+        self.update(RIGHT, x);
+        self.update(UP, -y);
+        self.update(DOWN, y);
+        let fire = self.fire.is_low().map_err(JoystickError::Fire)?;
+        let pressed = [
+            (Button::Up, self.directions & UP != 0),
+            (Button::Down, self.directions & DOWN != 0),
+            (Button::Left, self.directions & LEFT != 0),
+            (Button::Right, self.directions & RIGHT != 0),
+            (Button::A, fire),
+        ];
+        let mut keys: u16 = 0;
+        for (button, held) in pressed {
+            if let (true, Some(key)) = (held, self.mapping.get(button)) {
+                keys |= 1 << key;
+            }
+        }
+        Ok(keys)
+    }
+}
@@ -0,0 +1,167 @@
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::digital::v2::InputPin;
+
+use super::KeySource;
+
+/// Prefix sent before the scancode of a released key
+const BREAK: u8 = 0xf0;
+/// Prefix sent before the scancode of an extended key
+const EXTENDED: u8 = 0xe0;
+
+/// Set 2 scancodes for chip8 keys 0x0-0xF on the classic layout
+///
+/// ```text
+/// 1 2 3 4      1 2 3 C
+/// Q W E R  ->  4 5 6 D
+/// A S D F      7 8 9 E
+/// Z X C V      A 0 B F
+/// ```
+pub const PS2_LAYOUT: [u8; 16] = [
+    0x22, // X
+    0x16, // 1
+    0x1e, // 2
+    0x26, // 3
+    0x15, // Q
+    0x1d, // W
+    0x24, // E
+    0x1c, // A
+    0x1b, // S
+    0x23, // D
+    0x1a, // Z
+    0x21, // C
+    0x25, // 4
+    0x2d, // R
+    0x2b, // F
+    0x2a, // V
+];
+
+/// A PS/2 keyboard decoded from scancode set 2
+///
+/// Call [`on_falling_edge`](Self::on_falling_edge) from the interrupt
+/// handler of the clock pin. Each call samples the data pin and does a
+/// constant amount of work, so it is safe to run inside an ISR. Make and
+/// break codes are tracked so a key is reported for as long as it is held.
+///
+/// To share the key state between an ISR and the interpreter store
+/// [`keys`](Self::keys) into a [`SharedKeys`](super::SharedKeys).
+pub struct Ps2Keyboard<DI>
+where
+    DI: InputPin,
+{
+    data: DI,
+    layout: [u8; 16],
+    frame: u16,
+    bit: u8,
+    release: bool,
+    extended: bool,
+    keys: u16,
+}
+
+impl<DI, E> Ps2Keyboard<DI>
+where
+    DI: InputPin<Error = E>,
+{
+    /// Creates a Ps2Keyboard using [`PS2_LAYOUT`]
+    pub fn new(data: DI) -> Self {
+        Self::with_layout(data, PS2_LAYOUT)
+    }
+
+    /// Creates a Ps2Keyboard with a custom layout,
+    /// `layout[n]` is the scancode of chip8 key n
+    pub fn with_layout(data: DI, layout: [u8; 16]) -> Self {
+        Self {
+            data,
+            layout,
+            frame: 0,
+            bit: 0,
+            release: false,
+            extended: false,
+            keys: 0,
+        }
+    }
+
+    /// Samples the data pin, call this on every falling edge of the clock
+    pub fn on_falling_edge(&mut self) -> Result<(), E> {
+        let bit = self.data.is_high()?;
+        self.push_bit(bit);
+        Ok(())
+    }
+
+    /// Feeds a single bit of a PS/2 frame
+    ///
+    /// Useful when the data line is sampled by other means.
+    /// Frames with a bad start, parity or stop bit are dropped.
+    pub fn push_bit(&mut self, bit: bool) {
+        if self.bit == 0 && bit {
+            // Not a start bit, wait for one
+            return;
+        }
+        self.frame |= (bit as u16) << self.bit;
+        self.bit += 1;
+        if self.bit < 11 {
+            return;
+        }
+        let frame = self.frame;
+        self.frame = 0;
+        self.bit = 0;
+        let byte = (frame >> 1) as u8;
+        let parity = frame & (1 << 9) != 0;
+        let stop = frame & (1 << 10) != 0;
+        if stop && (byte.count_ones() % 2 == 1) != parity {
+            self.push_byte(byte);
+        }
+    }
+
+    /// Discards a partially received frame
+    ///
+    /// Call this when the clock has been idle for longer than a frame
+    /// to resynchronise after a glitch
+    pub fn resync(&mut self) {
+        self.frame = 0;
+        self.bit = 0;
+    }
+
+    /// Returns the held keys, bit n is set while key n is held down
+    pub fn keys(&self) -> u16 {
+        self.keys
+    }
+
+    /// Consumes the Ps2Keyboard and returns the data pin
+    pub fn release(self) -> DI {
+        self.data
+    }
+
+    fn push_byte(&mut self, byte: u8) {
+        match byte {
+            BREAK => self.release = true,
+            EXTENDED => self.extended = true,
+            code => {
+                if !self.extended {
+                    if let Some(key) = self.layout.iter().position(|&c| c == code) {
+                        if self.release {
+                            self.keys &= !(1 << key);
+                        } else {
+                            self.keys |= 1 << key;
+                        }
+                    }
+                }
+                self.release = false;
+                self.extended = false;
+            }
+        }
+    }
+}
+
+impl<DI, E> KeySource for Ps2Keyboard<DI>
+where
+    DI: InputPin<Error = E>,
+{
+    type Error = E;
+
+    fn scan<D>(&mut self, _delay: &mut D) -> Result<u16, E>
+    where
+        D: DelayMs<u32>,
+    {
+        Ok(self.keys)
+    }
+}
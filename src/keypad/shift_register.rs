@@ -0,0 +1,91 @@
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+use super::{KeySource, Polarity};
+
+/// A 16 button array read through two daisy chained
+/// 74HC165 parallel-in serial-out shift registers
+///
+/// Only three pins are needed:
+/// * `latch` is wired to SH/LD and pulsed low to capture the buttons
+/// * `clock` is wired to CLK and shifts the next bit onto `data`
+/// * `data` is wired to QH of the last register in the chain
+///
+/// The first bit shifted out is key 0 and the last is key 0xF
+pub struct ShiftRegisterKeyPad<L, C, DI>
+where
+    L: OutputPin,
+    C: OutputPin,
+    DI: InputPin,
+{
+    latch: L,
+    clock: C,
+    data: DI,
+    polarity: Polarity,
+}
+
+impl<L, C, DI, E> ShiftRegisterKeyPad<L, C, DI>
+where
+    L: OutputPin<Error = E>,
+    C: OutputPin<Error = E>,
+    DI: InputPin<Error = E>,
+{
+    /// Creates a ShiftRegisterKeyPad for buttons that pull their input low
+    pub fn new(latch: L, clock: C, data: DI) -> Result<Self, E> {
+        Self::with_polarity(latch, clock, data, Polarity::ActiveLow)
+    }
+
+    /// Creates a ShiftRegisterKeyPad with the given button polarity
+    ///
+    /// The latch is left high and the clock low
+    pub fn with_polarity(
+        mut latch: L,
+        mut clock: C,
+        data: DI,
+        polarity: Polarity,
+    ) -> Result<Self, E> {
+        latch.set_high()?;
+        clock.set_low()?;
+        Ok(Self {
+            latch,
+            clock,
+            data,
+            polarity,
+        })
+    }
+
+    /// Consumes the ShiftRegisterKeyPad and returns its pins
+    pub fn release(self) -> (L, C, DI) {
+        (self.latch, self.clock, self.data)
+    }
+}
+
+impl<L, C, DI, E> KeySource for ShiftRegisterKeyPad<L, C, DI>
+where
+    L: OutputPin<Error = E>,
+    C: OutputPin<Error = E>,
+    DI: InputPin<Error = E>,
+{
+    type Error = E;
+
+    fn scan<D>(&mut self, _delay: &mut D) -> Result<u16, E>
+    where
+        D: DelayMs<u32>,
+    {
+        self.latch.set_low()?;
+        self.latch.set_high()?;
+        let mut keys: u16 = 0;
+        for i in 0..16 {
+            let pressed = match self.polarity {
+                Polarity::ActiveLow => self.data.is_low()?,
+                Polarity::ActiveHigh => self.data.is_high()?,
+            };
+            if pressed {
+                keys |= 1 << i;
+            }
+            self.clock.set_high()?;
+            self.clock.set_low()?;
+        }
+        Ok(keys)
+    }
+}
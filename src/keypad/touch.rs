@@ -0,0 +1,170 @@
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyleBuilder, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use embedded_hal::blocking::delay::DelayMs;
+
+use super::KeySource;
+
+/// Most touch points read from a [`TouchController`] per scan
+const MAX_TOUCHES: usize = 5;
+
+/// Chip8 keys in the classic hex keypad layout, row by row
+const LAYOUT: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xc, //
+    0x4, 0x5, 0x6, 0xd, //
+    0x7, 0x8, 0x9, 0xe, //
+    0xa, 0x0, 0xb, 0xf, //
+];
+
+/// Labels drawn on the keys
+const LABELS: [&str; 16] = [
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "A", "B", "C", "D", "E", "F",
+];
+
+/// A touch panel able to report one or more touch points
+pub trait TouchController {
+    /// Error returned when the panel cannot be read
+    type Error;
+
+    /// Writes the current touch points in display coordinates
+    /// into `points` and returns how many were written
+    fn touches(&mut self, points: &mut [Point]) -> Result<usize, Self::Error>;
+}
+
+/// An on-screen 4x4 hex keypad
+///
+/// The keys evenly fill `area`, which would usually be the part
+/// of the display left empty beside or below the chip8 screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VirtualKeyPad {
+    area: Rectangle,
+}
+
+impl VirtualKeyPad {
+    /// Creates a VirtualKeyPad filling `area`
+    pub fn new(area: Rectangle) -> Self {
+        Self { area }
+    }
+
+    /// Returns the area covered by the keypad
+    pub fn area(&self) -> Rectangle {
+        self.area
+    }
+
+    /// Returns the chip8 key under a point on the display
+    pub fn key_at(&self, point: Point) -> Option<u8> {
+        if !self.area.contains(point) {
+            return None;
+        }
+        let offset = point - self.area.top_left;
+        let size = self.key_size();
+        let col = (offset.x as u32 / size.width.max(1)).min(3);
+        let row = (offset.y as u32 / size.height.max(1)).min(3);
+        Some(LAYOUT[(row * 4 + col) as usize])
+    }
+
+    /// Draws every key, keys set in `pressed` are highlighted
+    pub fn draw<D>(&self, display: &mut D, pressed: u16) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let size = self.key_size();
+        for (i, key) in LAYOUT.iter().enumerate() {
+            let top_left = self.area.top_left
+                + Point::new(
+                    (i as u32 % 4 * size.width) as i32,
+                    (i as u32 / 4 * size.height) as i32,
+                );
+            let held = pressed & (1 << key) != 0;
+            let (fill, text) = if held {
+                (Rgb565::WHITE, Rgb565::BLACK)
+            } else {
+                (Rgb565::BLACK, Rgb565::WHITE)
+            };
+            let rect = Rectangle::new(top_left, size);
+            rect.into_styled(
+                PrimitiveStyleBuilder::new()
+                    .fill_color(fill)
+                    .stroke_color(Rgb565::WHITE)
+                    .stroke_width(1)
+                    .build(),
+            )
+            .draw(display)?;
+            Text::with_text_style(
+                LABELS[*key as usize],
+                rect.center(),
+                MonoTextStyle::new(&FONT_6X10, text),
+                TextStyleBuilder::new()
+                    .alignment(Alignment::Center)
+                    .baseline(Baseline::Middle)
+                    .build(),
+            )
+            .draw(display)?;
+        }
+        Ok(())
+    }
+
+    fn key_size(&self) -> Size {
+        Size::new(self.area.size.width / 4, self.area.size.height / 4)
+    }
+}
+
+/// Turns touches on a [`VirtualKeyPad`] into chip8 key presses
+///
+/// Every finger on the panel presses the key under it,
+/// so several keys can be held at once
+pub struct TouchKeyPad<T>
+where
+    T: TouchController,
+{
+    controller: T,
+    keypad: VirtualKeyPad,
+}
+
+impl<T> TouchKeyPad<T>
+where
+    T: TouchController,
+{
+    /// Creates a TouchKeyPad for the keys of `keypad`
+    pub fn new(controller: T, keypad: VirtualKeyPad) -> Self {
+        Self { controller, keypad }
+    }
+
+    /// Returns the keypad being touched
+    pub fn keypad(&self) -> VirtualKeyPad {
+        self.keypad
+    }
+
+    /// Consumes the TouchKeyPad and returns the touch controller
+    pub fn release(self) -> T {
+        self.controller
+    }
+}
+
+impl<T> KeySource for TouchKeyPad<T>
+where
+    T: TouchController,
+{
+    type Error = T::Error;
+
+    fn scan<D>(&mut self, _delay: &mut D) -> Result<u16, T::Error>
+    where
+        D: DelayMs<u32>,
+    {
+        let mut points = [Point::zero(); MAX_TOUCHES];
+        let count = self.controller.touches(&mut points)?;
+        let mut keys: u16 = 0;
+        for point in points.iter().take(count) {
+            if let Some(key) = self.keypad.key_at(*point) {
+                keys |= 1 << key;
+            }
+        }
+        Ok(keys)
+    }
+}
@@ -1,25 +1,63 @@
 #![no_std]
 #![allow(unused_variables)]
 
+pub mod bus;
+pub mod cdp1802;
+pub mod chip8x;
+pub mod extension;
 pub mod fonts;
+pub mod framebuffer;
+pub mod instruction;
 pub mod keypad;
+pub mod megachip;
+pub mod movie;
+pub mod platform;
+pub mod rng;
 
+use bus::{Bus, Ram};
+use chip8x::{Chip8X, Chip8XInstruction};
 use embedded_graphics::{
     draw_target::DrawTarget, geometry::Point, pixelcolor::Rgb565, prelude::*, primitives::Rectangle,
 };
 use embedded_hal::blocking::delay::DelayMs;
-use embedded_hal::digital::v2::{InputPin, OutputPin};
-use keypad::KeyPad;
+use extension::{Machine, NoExtension, OpcodeExtension, PcAdvance};
+use fonts::Font;
+use framebuffer::{Framebuffer, Row, FULL_SIZE, PLANES};
+pub use instruction::{Instruction, InstructionSet};
+use keypad::{KeySource, VirtualKeyPad};
+use megachip::{MegaChip, MegaInstruction, MEGA_HEIGHT, MEGA_WIDTH};
+use platform::{IndexIncrement, Platform, Quirks};
 use rand::RngCore;
+use rng::Xorshift;
 
-const RAM_SIZE: usize = 4096;
 const NUM_REGISTERS: usize = 16;
 const STACK_SIZE: usize = 16;
-const FONT_START: usize = 0x50;
-const PROGRAM_START: usize = 0x200;
-const PROGRAM_END: usize = 0xFFF;
 const CHIP8_HEIGHT: usize = 32;
 const CHIP8_WIDTH: usize = 64;
+/// Largest height of any [`Resolution`]
+const MAX_HEIGHT: usize = 64;
+/// Largest width of any [`Resolution`]
+const MAX_WIDTH: usize = 128;
+/// The COSMAC VIP display buffer ends at the top of 4K
+const VIP_FRAMEBUFFER_END: usize = 0x1000;
+/// HIRES programs start with a jump to 0x260
+const HIRES_ENTRY: Opcode = (0x12, 0x60);
+/// Where HIRES programs continue once the hires patch has run
+const HIRES_START: u16 = 0x2c0;
+/// The machine language clear screen routine of the HIRES patch
+const HIRES_CLEAR: u16 = 0x230;
+/// Where the COSMAC VIP reserves memory for its stack, work area and display
+const VIP_RESERVED_START: usize = 0xea0;
+/// The COSMAC VIP stack grows down from this far into the reserved area
+const VIP_STACK_OFFSET: usize = 0x30;
+/// Number of SUPER-CHIP flag registers saved by `fx75`
+const NUM_FLAGS: usize = 16;
+/// Bytes in an XO-CHIP audio pattern
+const AUDIO_PATTERN_SIZE: usize = 16;
+/// XO-CHIP pitch that plays the audio pattern at 4000 bits per second
+const DEFAULT_PITCH: u8 = 64;
+/// Planes selected until a program uses `fn01`
+const DEFAULT_PLANES: u8 = 1;
 
 /// These bytes should be treated as half bytes
 pub type Nibble = u8;
@@ -28,32 +66,186 @@ pub type Opcode = (u8, u8);
 /// Opcode broken into four nibbles
 pub type OpcodeDecoded = (Nibble, Nibble, Nibble, Nibble);
 
-/// Combines the last three
-/// nibbles of an opcode into a u16
-fn nnn(opcode: OpcodeDecoded) -> u16 {
-    let mut nnn: u16 = 0;
-    nnn |= opcode.1 as u16;
-    nnn <<= 4;
-    nnn |= opcode.2 as u16;
-    nnn <<= 4;
-    nnn |= opcode.3 as u16;
-    nnn
+/// Errors that stop the interpreter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// An opcode that is not a known instruction was fetched
+    /// while the policy is [`UnknownOpcodePolicy::Halt`]
+    UnknownOpcode { address: u16, opcode: Opcode },
+    /// The memory [`Bus`] reported a failed access
+    /// while executing the instruction at `address`
+    BusFault { address: u16 },
+    /// The program stopped the interpreter with `00fd`
+    /// at `address`
+    Exited { address: u16 },
+    /// `00ee` returned at `address` with nothing on the stack
+    StackUnderflow { address: u16 },
+    /// `2nnn` called a subroutine at `address` with the stack full
+    StackOverflow { address: u16 },
+    /// The bus is smaller than the `required` bytes
+    /// of a peripheral being set
+    BusTooSmall { required: usize },
+    /// The keypad failed to scan for the instruction at `address`,
+    /// which is run again on the next `tick`
+    KeyPad { address: u16 },
+    /// `soft_reset` or `hard_reset` had no program from `load_rom`
+    /// to reload, the interpreter is reset with memory left as it was
+    NoProgram,
 }
 
-/// Combines the third and fourth
-/// nibbles of an opcode into a single byte
-fn nn(opcode: OpcodeDecoded) -> u8 {
-    (opcode.2 << 4) | opcode.3
+/// What to do when an opcode is not a known instruction
+#[derive(Clone, Copy, Debug)]
+pub enum UnknownOpcodePolicy {
+    /// Skip the opcode and carry on
+    Ignore,
+    /// Stop and return [`Error::UnknownOpcode`] from every `tick`
+    /// until the interpreter is reset, also taken for a `0nnn`
+    /// machine code call no extension handles, so running into
+    /// zeroed memory stops the interpreter
+    Halt,
+    /// Call the function with the address and opcode then skip it
+    Callback(fn(u16, Opcode)),
+}
+
+/// Where programs, fonts and the interpreter live in memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryMap {
+    /// Number of bytes of memory, the bus should be at least this
+    /// large, on a smaller bus only the bus is used
+    pub ram_size: usize,
+    /// Where programs are loaded and execution starts
+    pub program_start: u16,
+    /// Start of the area at the top of memory reserved for the
+    /// interpreter, programs end here
+    pub reserved_start: usize,
+    /// Where the small font is loaded
+    pub font_address: u16,
+    /// Where the large font is loaded
+    pub big_font_address: u16,
+}
+
+impl MemoryMap {
+    /// 4K with programs at 0x200 and the whole top of memory available
+    pub const CHIP_8: MemoryMap = MemoryMap {
+        ram_size: 0x1000,
+        program_start: 0x200,
+        reserved_start: 0x1000,
+        font_address: 0x50,
+        big_font_address: 0xa0,
+    };
+
+    /// The COSMAC VIP, which keeps its stack, work area
+    /// and display from 0xEA0 to the end of memory
+    pub const COSMAC_VIP: MemoryMap = MemoryMap {
+        reserved_start: 0xea0,
+        ..MemoryMap::CHIP_8
+    };
+
+    /// The ETI-660, which loads programs at 0x600
+    pub const ETI_660: MemoryMap = MemoryMap {
+        program_start: 0x600,
+        ..MemoryMap::CHIP_8
+    };
+
+    /// HIRES CHIP-8 images, which load at 0x200 with the 64x64
+    /// interpreter patch in front of the program at 0x2C0, the larger
+    /// display moves the reserved area down to 0xDA0
+    ///
+    /// Enable `set_hires_detection` to skip the patch
+    pub const HIRES: MemoryMap = MemoryMap {
+        reserved_start: 0xda0,
+        ..MemoryMap::CHIP_8
+    };
+
+    /// MEGA-CHIP with 24 bit addresses, this needs a bus of up to 16M
+    /// which also holds the [`MegaChip`] framebuffer and palette
+    pub const MEGA_CHIP: MemoryMap = MemoryMap {
+        ram_size: 0x100_0000,
+        reserved_start: 0x100_0000,
+        ..MemoryMap::CHIP_8
+    };
+
+    /// XO-CHIP with 64K of memory, this needs a bus such as `Ram<65536>`
+    pub const XO_CHIP: MemoryMap = MemoryMap {
+        ram_size: 0x10000,
+        reserved_start: 0x10000,
+        ..MemoryMap::CHIP_8
+    };
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        MemoryMap::CHIP_8
+    }
+}
+
+/// What memory holds when the interpreter is powered on by `hard_reset`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerOnMemory {
+    /// Every byte is 0
+    Zeros,
+    /// Every byte is 0xFF
+    Ones,
+    /// Pseudo random bytes from a seed, like the
+    /// RAM of a real COSMAC VIP at power on
+    Random(u32),
+}
+
+/// The size of the chip8 screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// The standard 64x32 screen
+    Standard,
+    /// HIRES CHIP-8, the 64x64 "two page" display
+    Hires,
+    /// CHIP-10, a 128x64 display
+    Chip10,
+}
+
+impl Resolution {
+    /// Returns the width in pixels
+    pub const fn width(&self) -> usize {
+        match self {
+            Resolution::Standard | Resolution::Hires => CHIP8_WIDTH,
+            Resolution::Chip10 => MAX_WIDTH,
+        }
+    }
+
+    /// Returns the height in pixels
+    pub const fn height(&self) -> usize {
+        match self {
+            Resolution::Standard => CHIP8_HEIGHT,
+            Resolution::Hires | Resolution::Chip10 => MAX_HEIGHT,
+        }
+    }
+}
+
+/// Where the interpreter keeps the display and call stack
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryLayout {
+    /// The display and stack are kept outside of chip8 memory
+    Separate,
+    /// The display and stack live in memory where the COSMAC VIP
+    /// interpreter keeps them, for programs that read them directly
+    ///
+    /// The display is a bitmap ending at 0xFFF, one bit per pixel with
+    /// the most significant bit leftmost. It starts at 0xF00 at the standard
+    /// resolution, 0xE00 for HIRES and 0xC00 for CHIP-10. The stack grows
+    /// down from 0x31 bytes below the display, 0xECF at the standard
+    /// resolution, with return addresses stored high byte first.
+    Vip,
 }
 
 /// A no_std Chip8 implementation
 ///
 /// #### Use this with your microcontroller:
 /// You need:
-/// * A microcontroller capable of generating random numbers
+/// * A microcontroller capable of generating random numbers,
+///   or the built in [`Xorshift`] set with `with_seed`
 /// * A display with a driver that implements the OriginDimensions and DrawTarget traits from embedded_graphics
 ///     * ie st7735
-/// * Enough free pins to create a button matrix (8 pins) and your display
+/// * Enough free pins to create a button matrix (8 pins) and your display,
+///   or any other [`KeySource`] from the [`keypad`] module
 ///
 /// #### Examples:
 /// <https://github.com/drewtchrist/chip8-pico>
@@ -62,17 +254,84 @@ fn nn(opcode: OpcodeDecoded) -> u8 {
 /// Timing should be handled by the peripherals of
 /// your hardware. This Chip8 implementation makes no attempts to manage
 /// the speed of itself.
-pub struct Chip8<D, O, I, R, DT>
+///
+/// #### Extensions:
+/// Custom instructions can be added with an [`OpcodeExtension`]
+/// registered through `with_extension`, machine language `0nnn`
+/// routines can be run with a [`Cdp1802`](cdp1802::Cdp1802)
+///
+/// #### Memory:
+/// Memory is 4K of internal [`Ram`] unless another [`Bus`]
+/// is set with `with_bus`. Where programs and fonts are placed
+/// is set by the [`MemoryMap`] given to `with_memory_map`
+///
+/// #### Platforms:
+/// `with_platform` and `reset_platform` set up the interpreter as a
+/// [`Platform`], selecting its instructions, quirks, resolution,
+/// memory and fonts in one call
+///
+/// #### Framebuffer:
+/// The last parameter `F` sets the words kept for the screen. It
+/// defaults to [`FULL_SIZE`], 2K bytes, which SUPER-CHIP and XO-CHIP
+/// need. The saving of the small framebuffer is opt in: programs that
+/// only use the 64x32 screen get it by calling
+/// `with_framebuffer::<CHIP8_SIZE>()` with
+/// [`CHIP8_SIZE`](framebuffer::CHIP8_SIZE)
+pub struct Chip8<D, K, R, DT, X = NoExtension, B = Ram, const F: usize = FULL_SIZE>
 where
     D: DrawTarget,
-    O: OutputPin,
-    I: InputPin,
+    K: KeySource,
     R: RngCore,
     DT: DelayMs<u32>,
+    X: OpcodeExtension,
+    B: Bus,
 {
     display: D,
-    keypad: KeyPad<O, I>,
-    memory: [u8; RAM_SIZE],
+    keypad: K,
+    memory: B,
+    rng: R,
+    delay: DT,
+    extension: X,
+    second_keypad: Option<K>,
+    framebuffer: Framebuffer<F>,
+    state: State,
+}
+
+/// What a [`Chip8`] was built from, returned by `release`
+pub struct Parts<D, K, R, DT, X, B> {
+    /// The display drawn on
+    pub display: D,
+    /// The keypad
+    pub keypad: K,
+    /// The random number generator
+    pub rng: R,
+    /// The delay used by the keypad
+    pub delay: DT,
+    /// The [`OpcodeExtension`] set with `with_extension`
+    pub extension: X,
+    /// The memory [`Bus`]
+    pub bus: B,
+    /// The CHIP-8X second keypad, if one was set
+    pub second_keypad: Option<K>,
+}
+
+/// A copy of the interpreter taken by `snapshot`
+///
+/// It holds the registers, stack, timers, screen and settings along with
+/// the random number generator, so a restored run draws the same random
+/// numbers. Memory is not included, save it from the bus alongside the
+/// snapshot. With [`MemoryLayout::Vip`] that includes the screen.
+#[derive(Clone)]
+pub struct Snapshot<R, const F: usize = FULL_SIZE> {
+    state: State,
+    framebuffer: Framebuffer<F>,
+    rng: R,
+}
+
+/// The interpreter state that does not depend on the type parameters,
+/// kept together so builders changing one of them move it in one go
+#[derive(Clone, Copy)]
+struct State {
     stack: [u16; STACK_SIZE],
     registers: [u8; NUM_REGISTERS],
     index: u16,
@@ -80,169 +339,828 @@ where
     stack_pointer: usize,
     delay_timer: u8,
     sound_timer: u8,
-    pixels: [[bool; CHIP8_HEIGHT]; CHIP8_WIDTH],
-    rng: R,
-    delay: DT,
     scale: (usize, usize),
     padding: usize,
     last_key: u8,
+    virtual_keypad: Option<VirtualKeyPad>,
+    drawn_keys: u16,
+    font_address: u16,
+    font_height: u8,
+    big_font_address: u16,
+    big_font_height: u8,
+    unknown_opcode_policy: UnknownOpcodePolicy,
+    halted: Option<Error>,
+    memory_layout: MemoryLayout,
+    memory_map: MemoryMap,
+    resolution: Resolution,
+    hires_detection: bool,
+    megachip: Option<MegaChip>,
+    chip8x: Option<Chip8X>,
+    platform: Option<Platform>,
+    instruction_set: InstructionSet,
+    quirks: Quirks,
+    flags: [u8; NUM_FLAGS],
+    planes: u8,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    power_on_memory: PowerOnMemory,
+    font: Option<Font>,
+    big_font: Option<Font>,
+    rom: Option<&'static [u8]>,
 }
 
-impl<D, O, I, R, DT> Chip8<D, O, I, R, DT>
+impl<D, K, R, DT> Chip8<D, K, R, DT>
 where
     D: OriginDimensions + DrawTarget<Color = Rgb565>,
-    O: OutputPin,
-    I: InputPin,
+    K: KeySource,
     R: RngCore,
     DT: DelayMs<u32>,
 {
-    pub fn new<E>(display: D, keypad: KeyPad<O, I>, rng: R, delay: DT) -> Self
-    where
-        D: OriginDimensions + DrawTarget<Color = Rgb565>,
-        O: OutputPin<Error = E>,
-        I: InputPin<Error = E>,
-        R: RngCore,
-        DT: DelayMs<u32>,
-    {
+    pub fn new(display: D, keypad: K, rng: R, delay: DT) -> Self {
+        Self::with_memory_map(display, keypad, rng, delay, MemoryMap::CHIP_8)
+    }
+
+    /// Creates a Chip8 using a different [`MemoryMap`]
+    ///
+    /// Maps larger than 4K also need a larger bus set with `with_bus`
+    pub fn with_memory_map(display: D, keypad: K, rng: R, delay: DT, map: MemoryMap) -> Self {
         let mut s = Self {
             display,
             keypad,
-            memory: [0; RAM_SIZE],
-            program_counter: PROGRAM_START as u16,
-            stack: [0; STACK_SIZE],
-            stack_pointer: 0,
-            registers: [0; NUM_REGISTERS],
-            index: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            pixels: [[false; CHIP8_HEIGHT]; CHIP8_WIDTH],
+            memory: Ram::new(),
             rng,
             delay,
-            scale: (1, 1),
-            padding: 0,
-            last_key: 0,
+            extension: NoExtension,
+            second_keypad: None,
+            framebuffer: Framebuffer::new(CHIP8_WIDTH, CHIP8_HEIGHT),
+            state: State {
+                program_counter: map.program_start,
+                stack: [0; STACK_SIZE],
+                stack_pointer: 0,
+                registers: [0; NUM_REGISTERS],
+                index: 0,
+                delay_timer: 0,
+                sound_timer: 0,
+                scale: (1, 1),
+                padding: 0,
+                last_key: 0,
+                virtual_keypad: None,
+                drawn_keys: 0,
+                font_address: map.font_address,
+                font_height: 5,
+                big_font_address: map.big_font_address,
+                big_font_height: 10,
+                unknown_opcode_policy: UnknownOpcodePolicy::Ignore,
+                halted: None,
+                memory_layout: MemoryLayout::Separate,
+                memory_map: map,
+                resolution: Resolution::Standard,
+                hires_detection: false,
+                megachip: None,
+                chip8x: None,
+                platform: None,
+                instruction_set: InstructionSet::Chip8,
+                quirks: Quirks::default(),
+                flags: [0; NUM_FLAGS],
+                planes: DEFAULT_PLANES,
+                audio_pattern: [0; AUDIO_PATTERN_SIZE],
+                pitch: DEFAULT_PITCH,
+                power_on_memory: PowerOnMemory::Zeros,
+                font: None,
+                big_font: None,
+                rom: None,
+            },
         };
         s._00e0();
         s
     }
 
+    /// Creates a Chip8 set up as a [`Platform`] with its fonts loaded
+    ///
+    /// XO-CHIP needs more memory than the internal 4K, for it create
+    /// the Chip8 with `new`, set a 64K bus with `with_bus` and
+    /// then call `reset_platform`
+    pub fn with_platform(display: D, keypad: K, rng: R, delay: DT, platform: Platform) -> Self {
+        let mut s = Self::new(display, keypad, rng, delay);
+        s.reset_platform(platform);
+        s
+    }
+}
+
+impl<D, K, DT> Chip8<D, K, Xorshift, DT>
+where
+    D: OriginDimensions + DrawTarget<Color = Rgb565>,
+    K: KeySource,
+    DT: DelayMs<u32>,
+{
+    /// Creates a Chip8 using the built in [`Xorshift`] random number
+    /// generator, for hardware without one or for repeatable runs
+    pub fn with_seed(display: D, keypad: K, delay: DT, seed: u64) -> Self {
+        Self::new(display, keypad, Xorshift::new(seed), delay)
+    }
+}
+
+impl<D, K, DT, X, B, const F: usize> Chip8<D, K, Xorshift, DT, X, B, F>
+where
+    D: OriginDimensions + DrawTarget<Color = Rgb565>,
+    K: KeySource,
+    DT: DelayMs<u32>,
+    X: OpcodeExtension,
+    B: Bus,
+{
+    /// Restarts the random numbers from a seed
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng.seed(seed);
+    }
+
+    /// Returns the state of the random number generator,
+    /// to save along with the rest of the machine
+    pub fn get_rng_state(&self) -> u64 {
+        self.rng.state()
+    }
+
+    /// Restores a state from `get_rng_state`
+    pub fn set_rng_state(&mut self, state: u64) {
+        self.rng.set_state(state);
+    }
+}
+
+impl<D, K, R, DT, X, B, const F: usize> Chip8<D, K, R, DT, X, B, F>
+where
+    D: OriginDimensions + DrawTarget<Color = Rgb565>,
+    K: KeySource,
+    R: RngCore + Clone,
+    DT: DelayMs<u32>,
+    X: OpcodeExtension,
+    B: Bus,
+{
+    /// Takes a [`Snapshot`] of the interpreter, for random number
+    /// generators that can be copied such as [`Xorshift`]
+    pub fn snapshot(&self) -> Snapshot<R, F> {
+        Snapshot {
+            state: self.state,
+            framebuffer: self.framebuffer,
+            rng: self.rng.clone(),
+        }
+    }
+
+    /// Goes back to a [`Snapshot`] and redraws the screen
+    pub fn restore(&mut self, snapshot: &Snapshot<R, F>) {
+        let previous = self.screen_area();
+        self.state = snapshot.state;
+        self.framebuffer = snapshot.framebuffer;
+        self.rng = snapshot.rng.clone();
+        self.display.fill_solid(&previous, Rgb565::BLACK).ok();
+        self.redraw();
+    }
+}
+
+impl<D, K, R, DT, X, B, const F: usize> Chip8<D, K, R, DT, X, B, F>
+where
+    D: OriginDimensions + DrawTarget<Color = Rgb565>,
+    K: KeySource,
+    R: RngCore,
+    DT: DelayMs<u32>,
+    X: OpcodeExtension,
+    B: Bus,
+{
+    /// Registers an [`OpcodeExtension`], replacing the current one
+    pub fn with_extension<X2>(self, extension: X2) -> Chip8<D, K, R, DT, X2, B, F>
+    where
+        X2: OpcodeExtension,
+    {
+        Chip8 {
+            display: self.display,
+            keypad: self.keypad,
+            memory: self.memory,
+            rng: self.rng,
+            delay: self.delay,
+            extension,
+            second_keypad: self.second_keypad,
+            framebuffer: self.framebuffer,
+            state: self.state,
+        }
+    }
+
+    /// Replaces the memory with another [`Bus`]
+    ///
+    /// Nothing is copied to the new bus so this should be
+    /// called before loading a font or program
+    pub fn with_bus<B2>(self, bus: B2) -> Chip8<D, K, R, DT, X, B2, F>
+    where
+        B2: Bus,
+    {
+        Chip8 {
+            display: self.display,
+            keypad: self.keypad,
+            memory: bus,
+            rng: self.rng,
+            delay: self.delay,
+            extension: self.extension,
+            second_keypad: self.second_keypad,
+            framebuffer: self.framebuffer,
+            state: self.state,
+        }
+    }
+
+    /// Replaces the framebuffer with one of `F2` words, see
+    /// [`Framebuffer`] for the sizes needed by each resolution
+    ///
+    /// Nothing is copied to the new framebuffer so this should
+    /// be called before running a program
+    pub fn with_framebuffer<const F2: usize>(self) -> Chip8<D, K, R, DT, X, B, F2> {
+        let mut framebuffer = Framebuffer::new(self.framebuffer.width(), self.framebuffer.height());
+        framebuffer.set_colors(self.framebuffer.colors());
+        Chip8 {
+            display: self.display,
+            keypad: self.keypad,
+            memory: self.memory,
+            rng: self.rng,
+            delay: self.delay,
+            extension: self.extension,
+            second_keypad: self.second_keypad,
+            framebuffer,
+            state: self.state,
+        }
+    }
+
+    /// Returns a mutable reference to the memory bus
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.memory
+    }
+
+    /// Returns a mutable reference to the display, for drawing
+    /// over it between ticks
+    ///
+    /// Call `redraw` to repaint the chip8 screen afterwards
+    pub fn display_mut(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    /// Returns a mutable reference to the keypad
+    pub fn keypad_mut(&mut self) -> &mut K {
+        &mut self.keypad
+    }
+
+    /// Returns a mutable reference to the random number generator
+    pub fn rng_mut(&mut self) -> &mut R {
+        &mut self.rng
+    }
+
+    /// Gives back everything the interpreter was built
+    /// from, dropping the interpreter
+    pub fn release(self) -> Parts<D, K, R, DT, X, B> {
+        Parts {
+            display: self.display,
+            keypad: self.keypad,
+            rng: self.rng,
+            delay: self.delay,
+            extension: self.extension,
+            bus: self.memory,
+            second_keypad: self.second_keypad,
+        }
+    }
+
+    /// Repaints the chip8 screen from the framebuffer, and the
+    /// virtual keypad if one is set, after the host has drawn
+    /// over the display
+    pub fn redraw(&mut self) {
+        match self.state.megachip.filter(|megachip| megachip.enabled()) {
+            Some(megachip) => self.mega_draw(megachip),
+            None => {
+                let (width, height) = self.screen_size();
+                self.redraw_region((0, width), (0, height));
+            }
+        }
+        if let Some(keypad) = self.state.virtual_keypad {
+            keypad.draw(&mut self.display, self.state.drawn_keys).ok();
+        }
+    }
+
+    /// Returns a mutable reference to the registered extension
+    pub fn extension_mut(&mut self) -> &mut X {
+        &mut self.extension
+    }
+
     /// Returns the current opcode
     ///
     /// Note that the `tick` method will update
     /// the program counter so this will return something
-    /// different depending on if it is called before or after `tick`
+    /// different depending on if it is called before or after `tick`.
+    /// Reads as 0 on a bus that cannot be peeked, such as [`bus::SpiRam`]
     pub fn get_current_op(&self) -> OpcodeDecoded {
-        self.decode(self.fetch_opcode())
+        instruction::nibbles(self.peek_opcode())
+    }
+
+    /// Returns the current instruction, or `None` if the
+    /// current opcode is not a known instruction
+    ///
+    /// Like `get_current_op` this depends on whether `tick` has been called
+    pub fn get_current_instruction(&self) -> Option<Instruction> {
+        Instruction::decode(self.peek_opcode())
     }
 
-    /// Returns a slice of the program memory
+    /// Returns a slice of the program memory, from the program
+    /// start to the reserved interpreter area
+    ///
+    /// Empty if the bus is not directly addressable
     pub fn get_program_memory(&self) -> &[u8] {
-        &self.memory[PROGRAM_START..PROGRAM_END]
+        match self.memory.as_slice() {
+            Some(memory) => {
+                let end = self.program_end().min(memory.len());
+                &memory[self.state.memory_map.program_start as usize..end]
+            }
+            None => &[],
+        }
+    }
+
+    /// Returns the memory map chosen at construction
+    pub fn get_memory_map(&self) -> MemoryMap {
+        self.state.memory_map
+    }
+
+    /// Reads a byte from memory
+    pub fn read_memory(&mut self, index: u16) -> u8 {
+        self.memory.read(index as usize)
     }
 
     /// Writes a byte to memory
     pub fn write_memory(&mut self, index: u16, data: u8) {
-        self.memory[index as usize] = data;
+        self.memory.write(index as usize, data);
     }
 
     /// Returns the value of PC or program counter
     pub fn get_program_counter(&self) -> u16 {
-        self.program_counter
+        self.state.program_counter
     }
 
     /// Sets the program counter
     pub fn set_program_counter(&mut self, value: u16) {
-        self.program_counter = value;
+        self.state.program_counter = value;
     }
 
     /// Returns the value in the I register
     pub fn get_index(&self) -> u16 {
-        self.index
+        self.state.index
     }
 
     /// Sets the index
     pub fn set_index(&mut self, value: u16) {
-        self.index = value;
+        self.state.index = value;
     }
 
     /// Returns the stack
+    ///
+    /// With [`MemoryLayout::Vip`] the stack is kept in memory
+    /// and this returns zeros
     pub fn get_stack(&self) -> [u16; STACK_SIZE] {
-        self.stack
+        self.state.stack
     }
 
     /// Writes a u16 to the stack
     pub fn write_stack(&mut self, index: u8, data: u16) {
-        self.stack[index as usize] = data;
+        self.state.stack[index as usize] = data;
     }
 
     /// Gets the stack pointer
     pub fn get_stack_pointer(&self) -> usize {
-        self.stack_pointer
+        self.state.stack_pointer
     }
-    
+
     /// Sets the stack pointer
     pub fn set_stack_pointer(&mut self, value: usize) {
-        self.stack_pointer = value;
+        self.state.stack_pointer = value;
     }
 
     /// Returns the data registers
     pub fn get_registers(&self) -> [u8; NUM_REGISTERS] {
-        self.registers
+        self.state.registers
     }
 
     /// Writes a byte to a register
     pub fn write_register(&mut self, index: u8, data: u8) {
-        self.registers[index as usize] = data;
+        self.state.registers[index as usize] = data;
     }
 
     /// Gets the last key pressed
     pub fn get_last_key(&self) -> u8 {
-        self.last_key
+        self.state.last_key
     }
 
-    /// Copies a chip8 font into memory starting at 0x50
-    pub fn load_font<const S: usize>(&mut self, font: [u8; S]) {
-        let mut current = FONT_START;
-        for byte in font {
-            self.memory[current] = byte;
-            current += 1;
-        }
+    /// Copies a small font into memory for use by `fx29`
+    ///
+    /// The font is placed at the [`MemoryMap`] font address unless
+    /// moved with `set_font_addresses`
+    pub fn load_font(&mut self, font: Font) {
+        self.state.font = Some(font);
+        self.memory
+            .write_slice(self.state.font_address as usize, font.data());
+        self.state.font_height = font.height();
+    }
+
+    /// Copies a large font into memory for use by `fx30`
+    ///
+    /// The font is placed at the [`MemoryMap`] large font address unless
+    /// moved with `set_font_addresses`
+    pub fn load_big_font(&mut self, font: Font) {
+        self.state.big_font = Some(font);
+        self.memory
+            .write_slice(self.state.big_font_address as usize, font.data());
+        self.state.big_font_height = font.height();
+    }
+
+    /// Sets where `load_font` and `load_big_font` place their fonts
+    /// and where `fx29` and `fx30` look for them
+    pub fn set_font_addresses(&mut self, small: u16, big: u16) {
+        self.state.font_address = small;
+        self.state.big_font_address = big;
+    }
+
+    /// Returns the addresses of the small and large fonts
+    pub fn get_font_addresses(&self) -> (u16, u16) {
+        (self.state.font_address, self.state.big_font_address)
     }
 
     /// Copies a chip8 program into memory
+    ///
+    /// Bytes that would run into the reserved interpreter area are not
+    /// loaded. The program is not kept, so `soft_reset` and `hard_reset`
+    /// return [`Error::NoProgram`], give it to `soft_reset_with` or
+    /// `hard_reset_with` instead or load a `'static` program with `load_rom`
     pub fn load_program<const S: usize>(&mut self, program: &[u8; S]) {
-        let mut current = PROGRAM_START;
-        for byte in program {
-            self.memory[current] = *byte;
-            current += 1;
-        }
+        self.state.rom = None;
+        self.write_program(program);
+    }
+
+    /// Copies a chip8 program into memory and keeps it
+    /// to reload on `soft_reset` and `hard_reset`
+    ///
+    /// Bytes that would run into the reserved interpreter area are not loaded
+    pub fn load_rom(&mut self, rom: &'static [u8]) {
+        self.state.rom = Some(rom);
+        self.write_program(rom);
+    }
+
+    /// Copies a program to the program start
+    fn write_program(&mut self, program: &[u8]) {
+        let start = self.state.memory_map.program_start as usize;
+        let length = program.len().min(self.program_end().saturating_sub(start));
+        self.memory.write_slice(start, &program[..length]);
     }
 
     /// Sets an (x, y) scale to increase the drawing on the
     /// display since Chip8 display is meant to be 64x32
     ///
+    /// The scale is for the standard resolution, at higher resolutions
+    /// it is reduced so the screen covers the same area where possible.
     /// The default scale is (1, 1)
     pub fn set_scale(&mut self, scale: (usize, usize)) {
-        self.scale = scale;
+        self.state.scale = scale;
     }
 
     /// Sets a value to add padding to the left side of the display
     pub fn set_padding(&mut self, left_padding: usize) {
-        self.padding = left_padding;
+        self.state.padding = left_padding;
+    }
+
+    /// Sets an on-screen keypad to draw in the part of the display
+    /// not used by the chip8 screen, for use with a
+    /// [`TouchKeyPad`](keypad::TouchKeyPad)
+    ///
+    /// While a keypad is set clearing the screen only clears
+    /// the chip8 screen area and held keys are highlighted
+    pub fn set_virtual_keypad(&mut self, keypad: Option<VirtualKeyPad>) {
+        self.state.virtual_keypad = keypad;
+        self.state.drawn_keys = 0;
+        if let Some(keypad) = self.state.virtual_keypad {
+            keypad.draw(&mut self.display, 0).ok();
+        }
+    }
+
+    /// Sets what happens when an opcode is not a known instruction
+    ///
+    /// The default is [`UnknownOpcodePolicy::Ignore`]
+    pub fn set_unknown_opcode_policy(&mut self, policy: UnknownOpcodePolicy) {
+        self.state.unknown_opcode_policy = policy;
+    }
+
+    /// Sets where the display and call stack are kept
+    ///
+    /// This should be called before loading a program, switching layout
+    /// clears the screen and empties the stack
+    pub fn set_memory_layout(&mut self, layout: MemoryLayout) {
+        self.state.memory_layout = layout;
+        self.state.stack = [0; STACK_SIZE];
+        self.state.stack_pointer = 0;
+        self.framebuffer.clear(!0);
+        self._00e0();
+    }
+
+    /// Sets the screen resolution, clearing the screen
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.switch_resolution(resolution, true);
+    }
+
+    /// Returns the screen resolution
+    pub fn get_resolution(&self) -> Resolution {
+        self.state.resolution
+    }
+
+    /// Sets whether HIRES programs are detected when they start
+    ///
+    /// When enabled a program beginning with `1260` at 0x200 switches to
+    /// [`Resolution::Hires`] and continues at 0x2C0, skipping the
+    /// interpreter patch it was written for. `0230` then clears the screen
+    /// as the patch does while the resolution is HIRES.
+    ///
+    /// Detection is disabled by default and enabled
+    /// by [`Platform::HiresChip8`]
+    pub fn set_hires_detection(&mut self, enabled: bool) {
+        self.state.hires_detection = enabled;
+    }
+
+    /// Sets MEGA-CHIP support, which programs switch on with `0011`
+    ///
+    /// Without it MEGA-CHIP instructions are `0nnn` calls. Returns
+    /// [`Error::BusTooSmall`] and leaves support unchanged if the bus
+    /// cannot hold the framebuffer and palette
+    pub fn set_megachip(&mut self, megachip: Option<MegaChip>) -> Result<(), Error> {
+        if let Some(megachip) = megachip {
+            let required = megachip.required_memory();
+            if self.memory.len() < required {
+                return Err(Error::BusTooSmall { required });
+            }
+        }
+        self.state.megachip = megachip;
+        Ok(())
+    }
+
+    /// Returns the MEGA-CHIP state if support is set
+    pub fn get_megachip(&self) -> Option<&MegaChip> {
+        self.state.megachip.as_ref()
+    }
+
+    /// Returns the next sample of the MEGA-CHIP digitized sound
+    /// for output at `output_rate` samples per second, or `None`
+    /// when no sound is playing
+    ///
+    /// This should be called from the audio timer of the hardware
+    pub fn next_sample(&mut self, output_rate: u32) -> Option<u8> {
+        let megachip = self.state.megachip.as_mut()?;
+        megachip.next_sample(output_rate, &mut self.memory)
+    }
+
+    /// Sets CHIP-8X mode, which colors the screen and
+    /// adds the CHIP-8X instructions in place of `bnnn`
+    ///
+    /// Setting or clearing it clears the screen
+    pub fn set_chip8x(&mut self, chip8x: Option<Chip8X>) {
+        self.state.chip8x = chip8x;
+        self._00e0();
+    }
+
+    /// Returns the CHIP-8X color state if CHIP-8X mode is set
+    pub fn get_chip8x(&self) -> Option<&Chip8X> {
+        self.state.chip8x.as_ref()
+    }
+
+    /// Sets the second keypad read by the CHIP-8X `exf2` and `exf5`,
+    /// without one no keys are held on it
+    pub fn set_second_keypad(&mut self, keypad: Option<K>) {
+        self.second_keypad = keypad;
+    }
+
+    /// Returns where the display and call stack are kept
+    pub fn get_memory_layout(&self) -> MemoryLayout {
+        self.state.memory_layout
+    }
+
+    /// Resets the interpreter and sets it up as a [`Platform`]
+    ///
+    /// This selects the platform's memory map, instruction set, quirks,
+    /// memory layout and resolution, and loads its fonts. The program
+    /// must be loaded again afterwards.
+    pub fn reset_platform(&mut self, platform: Platform) {
+        let map = platform.memory_map();
+        self.state.memory_map = map;
+        self.reset();
+        self.set_font_addresses(map.font_address, map.big_font_address);
+        self.load_font(platform.font());
+        if let Some(font) = platform.big_font() {
+            self.load_big_font(font);
+        }
+        self.state.platform = Some(platform);
+        self.state.instruction_set = platform.instruction_set();
+        self.state.quirks = platform.quirks();
+        self.set_memory_layout(platform.memory_layout());
+        self.set_resolution(platform.resolution());
+        self.state.hires_detection = platform == Platform::HiresChip8;
+    }
+
+    /// Returns the platform set with `with_platform` or `reset_platform`
+    pub fn get_platform(&self) -> Option<Platform> {
+        self.state.platform
+    }
+
+    /// Sets the instructions `tick` recognises
+    ///
+    /// The default is [`InstructionSet::Chip8`]
+    pub fn set_instruction_set(&mut self, instruction_set: InstructionSet) {
+        self.state.instruction_set = instruction_set;
+    }
+
+    /// Returns the instructions `tick` recognises
+    pub fn get_instruction_set(&self) -> InstructionSet {
+        self.state.instruction_set
+    }
+
+    /// Sets the quirks, replacing those of the platform
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.state.quirks = quirks;
+    }
+
+    /// Returns the quirks in use
+    pub fn get_quirks(&self) -> Quirks {
+        self.state.quirks
+    }
+
+    /// Sets the colors of XO-CHIP pixels, indexed by the planes set
+    /// with the first plane as bit 0
+    pub fn set_plane_colors(&mut self, colors: [Rgb565; 4]) {
+        self.framebuffer.set_colors(colors);
+        let (width, height) = self.screen_size();
+        self.redraw_region((0, width), (0, height));
+    }
+
+    /// Returns the screen, to read pixels or draw it elsewhere
+    ///
+    /// With [`MemoryLayout::Vip`] call `sync_framebuffer` first to copy
+    /// the screen from memory. MEGA-CHIP mode draws into memory and is
+    /// not included.
+    pub fn get_framebuffer(&self) -> &Framebuffer<F> {
+        &self.framebuffer
+    }
+
+    /// Copies the screen from memory into the framebuffer with
+    /// [`MemoryLayout::Vip`], where programs can draw by writing memory.
+    /// Does nothing with other layouts
+    pub fn sync_framebuffer(&mut self) {
+        if self.state.memory_layout == MemoryLayout::Vip {
+            for y in 0..self.state.resolution.height() {
+                let row = self.row(0, y);
+                self.framebuffer.set_row(0, y, row);
+                self.framebuffer.set_row(1, y, 0);
+            }
+        }
+    }
+
+    /// Returns the SUPER-CHIP flag registers saved by `fx75`
+    pub fn get_flags(&self) -> [u8; NUM_FLAGS] {
+        self.state.flags
+    }
+
+    /// Returns the XO-CHIP audio pattern loaded by `f002`
+    ///
+    /// The 128 bits are played most significant first, looping
+    /// while the sound timer is running
+    pub fn get_audio_pattern(&self) -> [u8; AUDIO_PATTERN_SIZE] {
+        self.state.audio_pattern
+    }
+
+    /// Returns the XO-CHIP pitch set by `fx3a`
+    ///
+    /// The audio pattern plays at `4000 * 2^((pitch - 64) / 48)` bits per second
+    pub fn get_pitch(&self) -> u8 {
+        self.state.pitch
+    }
+
+    /// Returns the error that halted the interpreter, if any
+    pub fn get_error(&self) -> Option<Error> {
+        self.state.halted
     }
 
     /// Resets the chip8 interpreter
-    /// by clearing all memory and registers
+    /// by clearing all memory, registers and the display
+    ///
+    /// The fonts and program must be loaded again afterwards,
+    /// unlike `soft_reset` and `hard_reset` which reload them
     pub fn reset(&mut self) {
-        self.memory = [0; RAM_SIZE];
-        self.program_counter = PROGRAM_START as u16;
-        self.stack = [0; STACK_SIZE];
-        self.stack_pointer = 0;
-        self.registers = [0; NUM_REGISTERS];
-        self.index = 0;
-        self.delay_timer = 0;
-        self.sound_timer = 0;
-        self.pixels = [[false; CHIP8_HEIGHT]; CHIP8_WIDTH];
+        self.memory.fill(0);
+        self.state.font = None;
+        self.state.big_font = None;
+        self.state.rom = None;
+        self.clear_state();
+        self.clear_display();
+    }
+
+    /// Restarts the loaded program
+    ///
+    /// Registers, stack, timers and the screen are cleared and the fonts
+    /// and the program loaded with `load_rom` are copied into memory
+    /// again, the rest of memory and the flag registers are kept. The
+    /// resolution goes back to that of the platform if one is set.
+    ///
+    /// Returns [`Error::NoProgram`] when the program came from
+    /// `load_program` or `soft_reset_with`, which are not kept,
+    /// use `soft_reset_with` to restart those
+    pub fn soft_reset(&mut self) -> Result<(), Error> {
+        self.restart();
+        let rom = self.state.rom.ok_or(Error::NoProgram)?;
+        self.write_program(rom);
+        Ok(())
+    }
+
+    /// Restarts as `soft_reset` does, loading `program`
+    /// in place of any program loaded with `load_rom`
+    pub fn soft_reset_with(&mut self, program: &[u8]) {
+        self.state.rom = None;
+        self.restart();
+        self.write_program(program);
+    }
+
+    /// Clears the machine and reloads the fonts for `soft_reset`
+    fn restart(&mut self) {
+        self.clear_state();
+        if let Some(megachip) = self.state.megachip.as_mut() {
+            megachip.set_enabled(false);
+        }
+        if self.state.chip8x.is_some() {
+            self.state.chip8x = Some(Chip8X::new());
+        }
+        let resolution = self
+            .state
+            .platform
+            .map_or(self.state.resolution, |platform| platform.resolution());
+        self.set_resolution(resolution);
+        if let Some(font) = self.state.font {
+            self.load_font(font);
+        }
+        if let Some(font) = self.state.big_font {
+            self.load_big_font(font);
+        }
+    }
+
+    /// Powers the interpreter off and on again
+    ///
+    /// Memory is filled with the [`PowerOnMemory`] pattern
+    /// then this restarts as `soft_reset` does
+    pub fn hard_reset(&mut self) -> Result<(), Error> {
+        self.power_on();
+        self.soft_reset()
+    }
+
+    /// Powers the interpreter off and on again as `hard_reset`
+    /// does, then restarts as `soft_reset_with` does
+    pub fn hard_reset_with(&mut self, program: &[u8]) {
+        self.power_on();
+        self.soft_reset_with(program);
+    }
+
+    /// Fills memory with the [`PowerOnMemory`] pattern
+    fn power_on(&mut self) {
+        match self.state.power_on_memory {
+            PowerOnMemory::Zeros => self.memory.fill(0),
+            PowerOnMemory::Ones => self.memory.fill(0xff),
+            PowerOnMemory::Random(seed) => {
+                let mut rng = Xorshift::new(seed as u64);
+                for address in 0..self.memory.len() {
+                    self.memory.write(address, rng.next_u32() as u8);
+                }
+            }
+        }
+    }
+
+    /// Sets what memory holds after `hard_reset`
+    ///
+    /// The default is [`PowerOnMemory::Zeros`]
+    pub fn set_power_on_memory(&mut self, pattern: PowerOnMemory) {
+        self.state.power_on_memory = pattern;
+    }
+
+    /// Clears everything but memory
+    /// Fills the display with the background, leaving any virtual keypad
+    fn clear_display(&mut self) {
+        let rect = &match self.state.virtual_keypad {
+            Some(_) => self.screen_area(),
+            None => Rectangle::new(Point::new(0, 0), self.display.size()),
+        };
+        let background = self.pixel_color(0, 0, 0);
+        self.display.fill_solid(rect, background).ok();
+    }
+
+    fn clear_state(&mut self) {
+        self.state.program_counter = self.state.memory_map.program_start;
+        self.state.stack = [0; STACK_SIZE];
+        self.state.stack_pointer = 0;
+        self.state.registers = [0; NUM_REGISTERS];
+        self.state.index = 0;
+        self.state.delay_timer = 0;
+        self.state.sound_timer = 0;
+        self.framebuffer.clear(!0);
+        self.state.halted = None;
+        self.state.planes = DEFAULT_PLANES;
+        self.state.audio_pattern = [0; AUDIO_PATTERN_SIZE];
+        self.state.pitch = DEFAULT_PITCH;
     }
 
     /// This should be called within a loop
@@ -250,328 +1168,989 @@ where
     ///
     /// Note there is no time management here
     /// so this Chip8 will run very fast without a delay
-    pub fn tick(&mut self) {
+    ///
+    /// Once an error is returned the interpreter is halted and
+    /// returns the same error until it is reset, except for
+    /// [`Error::KeyPad`] which leaves it on the same instruction
+    pub fn tick(&mut self) -> Result<(), Error> {
+        if let Some(error) = self.state.halted {
+            return Err(error);
+        }
+        let address = self.state.program_counter;
         let opcode = self.fetch_opcode();
-        let opcode_decoded = self.decode(opcode);
-        self.execute(opcode_decoded);
+        if self.state.hires_detection && address == 0x200 && opcode == HIRES_ENTRY {
+            self.set_resolution(Resolution::Hires);
+            self.state.program_counter = HIRES_START;
+            self.update_timers();
+            return Ok(());
+        }
+        if let Some(megachip) = self.state.megachip {
+            match MegaInstruction::decode(opcode) {
+                Some(instruction) if megachip.enabled() || instruction == MegaInstruction::On => {
+                    self.execute_mega(instruction);
+                    self.update_timers();
+                    return self.check_bus(address);
+                }
+                _ => {}
+            }
+        }
+        if self.state.chip8x.is_some() {
+            if let Some(instruction) = Chip8XInstruction::decode(opcode) {
+                self.execute_chip8x(instruction)?;
+                self.update_timers();
+                return self.check_bus(address);
+            }
+        }
+        let instruction = match Instruction::decode(opcode) {
+            Some(instruction) if !self.state.instruction_set.supports(instruction) => {
+                match instruction::nibbles(opcode).0 {
+                    0x0 => Some(Instruction::SysCall(u16::from_be_bytes([
+                        opcode.0, opcode.1,
+                    ]))),
+                    _ => None,
+                }
+            }
+            instruction => instruction,
+        };
+        if self.state.resolution == Resolution::Hires
+            && instruction == Some(Instruction::SysCall(HIRES_CLEAR))
+        {
+            self.execute(Some(Instruction::Cls))?;
+            return self.check_bus(address);
+        }
+        if let None | Some(Instruction::SysCall(_)) = instruction {
+            let mut machine = Machine {
+                registers: &mut self.state.registers,
+                index: &mut self.state.index,
+                memory: &mut self.memory,
+                program_counter: self.state.program_counter,
+            };
+            if let Some(advance) = self.extension.execute(opcode, &mut machine) {
+                self.state.program_counter = match advance {
+                    PcAdvance::Next => self.state.program_counter + 2,
+                    PcAdvance::Skip => self.state.program_counter + 4,
+                    PcAdvance::Jump(target) => target,
+                };
+                self.update_timers();
+                return self.check_bus(address);
+            }
+        }
+        if let None | Some(Instruction::SysCall(_)) = instruction {
+            match (self.state.unknown_opcode_policy, instruction) {
+                (UnknownOpcodePolicy::Halt, _) => {
+                    let error = Error::UnknownOpcode {
+                        address: self.state.program_counter,
+                        opcode,
+                    };
+                    self.state.halted = Some(error);
+                    return Err(error);
+                }
+                (UnknownOpcodePolicy::Callback(callback), None) => {
+                    callback(self.state.program_counter, opcode)
+                }
+                _ => {}
+            }
+        }
+        self.execute(instruction)?;
+        if let Some(error) = self.state.halted {
+            return Err(error);
+        }
+        self.check_bus(address)
+    }
+
+    /// Returns the end of the program area
+    fn program_end(&self) -> usize {
+        self.state.memory_map.reserved_start.min(self.ram_size())
+    }
+
+    /// Returns the size of memory, the smaller of the memory map
+    /// and the bus so a map larger than the bus cannot reach past it
+    fn ram_size(&self) -> usize {
+        self.state.memory_map.ram_size.min(self.memory.len())
+    }
+
+    /// Halts with [`Error::BusFault`] if the bus reports a failed access
+    fn check_bus(&mut self, address: u16) -> Result<(), Error> {
+        if self.memory.faulted() {
+            let error = Error::BusFault { address };
+            self.state.halted = Some(error);
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes taken by the instruction at `address`,
+    /// 4 for the XO-CHIP `f000 nnnn` and 2 for everything else
+    fn instruction_length(&mut self, address: u16) -> u16 {
+        let mut opcode = [0; 2];
+        self.memory.read_slice(address as usize, &mut opcode);
+        match (self.state.instruction_set, opcode) {
+            (InstructionSet::XoChip, [0xf0, 0x00]) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Changes the resolution, clearing the screen or
+    /// redrawing what is already drawn
+    fn switch_resolution(&mut self, resolution: Resolution, clear: bool) {
+        let previous = self.screen_area();
+        self.state.resolution = resolution;
+        self.framebuffer
+            .resize(resolution.width(), resolution.height());
+        if self.state.virtual_keypad.is_some() || !clear {
+            self.display.fill_solid(&previous, Rgb565::BLACK).ok();
+        }
+        match clear {
+            true => self._00e0(),
+            false => {
+                let (width, height) = self.screen_size();
+                self.redraw_region((0, width), (0, height));
+            }
+        }
     }
 
-    fn fetch_opcode(&self) -> Opcode {
-        let opcode: Opcode = (
-            self.memory[self.program_counter as usize],
-            self.memory[(self.program_counter + 1) as usize],
+    /// Moves the selected planes by (dx, dy) pixels, filling
+    /// with clear pixels, then redraws the screen
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (
+            self.state.resolution.width(),
+            self.state.resolution.height(),
         );
-        opcode
+        let planes = self.plane_mask();
+        for plane in (0..PLANES).filter(|plane| planes & (1 << plane) != 0) {
+            // Walk against the direction of the scroll so rows
+            // are read before they are overwritten
+            for line in 0..height {
+                let y = if dy > 0 { height - 1 - line } else { line };
+                let from = y as isize - dy;
+                let row = match (0..height as isize).contains(&from) {
+                    true => self.row(plane, from as usize),
+                    false => 0,
+                };
+                let row = match dx {
+                    0.. => row >> dx,
+                    _ => row << -dx,
+                };
+                self.set_row(plane, y, row);
+            }
+        }
+        self.redraw_region((0, width), (0, height));
     }
 
-    fn decode(&self, opcode: Opcode) -> OpcodeDecoded {
-        (
-            opcode.0 >> 4,
-            opcode.0.rotate_left(4) >> 4,
-            opcode.1 >> 4,
-            opcode.1.rotate_left(4) >> 4,
-        )
+    /// Returns the planes drawn on, only the first
+    /// plane outside of XO-CHIP
+    fn plane_mask(&self) -> u8 {
+        match self.state.instruction_set {
+            InstructionSet::XoChip => self.state.planes,
+            _ => DEFAULT_PLANES,
+        }
+    }
+
+    fn fetch_opcode(&mut self) -> Opcode {
+        let mut opcode = [0; 2];
+        self.memory
+            .read_slice(self.state.program_counter as usize, &mut opcode);
+        (opcode[0], opcode[1])
+    }
+
+    /// Reads the opcode at the program counter without touching the bus
+    fn peek_opcode(&self) -> Opcode {
+        let address = self.state.program_counter as usize;
+        let peek = |address| self.memory.peek(address).unwrap_or(0);
+        (peek(address), peek(address + 1))
     }
 
-    fn execute(&mut self, opcode: OpcodeDecoded) {
+    fn execute(&mut self, instruction: Option<Instruction>) -> Result<(), Error> {
+        use Instruction::*;
         let mut pc_increment: u16 = 2;
         let mut update_pc: bool = true;
         let mut skip_instruction: bool = false;
-        match opcode {
-            (0x0, _, _, 0x0) => self._00e0(),
-            (0x0, _, _, 0xe) => self._00ee(),
-            (0x1, _, _, _) => {
-                self._1nnn(nnn(opcode));
+        match instruction {
+            Some(Cls) => self._00e0(),
+            Some(Ret) => {
+                if let Err(error) = self._00ee() {
+                    self.state.halted = Some(error);
+                    update_pc = false;
+                }
+            }
+            Some(SysCall(_)) => {}
+            Some(ScrollDown(n)) => self.scroll(0, n as isize),
+            Some(ScrollUp(n)) => self.scroll(0, -(n as isize)),
+            Some(ScrollRight) => self.scroll(4, 0),
+            Some(ScrollLeft) => self.scroll(-4, 0),
+            Some(Exit) => {
+                self.state.halted = Some(Error::Exited {
+                    address: self.state.program_counter,
+                });
                 update_pc = false;
             }
-            (0x2, _, _, _) => {
-                self._2nnn(nnn(opcode));
+            Some(Low) => {
+                self.switch_resolution(Resolution::Standard, self.state.quirks.clear_on_resolution)
+            }
+            Some(High) => {
+                self.switch_resolution(Resolution::Chip10, self.state.quirks.clear_on_resolution)
+            }
+            Some(Jp(nnn)) => {
+                self._1nnn(nnn);
+                update_pc = false;
+            }
+            Some(Call(nnn)) => {
+                if let Err(error) = self._2nnn(nnn) {
+                    self.state.halted = Some(error);
+                }
                 update_pc = false;
             }
-            (0x3, _, _, _) => skip_instruction = self._3xnn(opcode.1, nn(opcode)),
-            (0x4, _, _, _) => skip_instruction = self._4xnn(opcode.1, nn(opcode)),
-            (0x5, _, _, _) => skip_instruction = self._5xy0(opcode.1, opcode.2),
-            (0x6, _, _, _) => self._6xnn(opcode.1, nn(opcode)),
-            (0x7, _, _, _) => self._7xnn(opcode.1, nn(opcode)),
-            (0x8, _, _, 0x0) => self._8xy0(opcode.1, opcode.2),
-            (0x8, _, _, 0x1) => self._8xy1(opcode.1, opcode.2),
-            (0x8, _, _, 0x2) => self._8xy2(opcode.1, opcode.2),
-            (0x8, _, _, 0x3) => self._8xy3(opcode.1, opcode.2),
-            (0x8, _, _, 0x4) => self._8xy4(opcode.1, opcode.2),
-            (0x8, _, _, 0x5) => self._8xy5(opcode.1, opcode.2),
-            (0x8, _, _, 0x6) => self._8xy6(opcode.1, opcode.2),
-            (0x8, _, _, 0x7) => self._8xy7(opcode.1, opcode.2),
-            (0x8, _, _, 0xe) => self._8xye(opcode.1, opcode.2),
-            (0x9, _, _, _) => skip_instruction = self._9xy0(opcode.1, opcode.2),
-            (0xa, _, _, _) => self._annn(nnn(opcode)),
-            (0xb, _, _, _) => {
-                self._bnnn(nnn(opcode));
+            Some(SeByte { x, nn }) => skip_instruction = self._3xnn(x, nn),
+            Some(SneByte { x, nn }) => skip_instruction = self._4xnn(x, nn),
+            Some(SeReg { x, y }) => skip_instruction = self._5xy0(x, y),
+            Some(SaveRange { x, y }) => self._5xy2(x, y),
+            Some(LoadRange { x, y }) => self._5xy3(x, y),
+            Some(LdByte { x, nn }) => self._6xnn(x, nn),
+            Some(AddByte { x, nn }) => self._7xnn(x, nn),
+            Some(LdReg { x, y }) => self._8xy0(x, y),
+            Some(Or { x, y }) => self._8xy1(x, y),
+            Some(And { x, y }) => self._8xy2(x, y),
+            Some(Xor { x, y }) => self._8xy3(x, y),
+            Some(AddReg { x, y }) => self._8xy4(x, y),
+            Some(Sub { x, y }) => self._8xy5(x, y),
+            Some(Shr { x, y }) => self._8xy6(x, y),
+            Some(Subn { x, y }) => self._8xy7(x, y),
+            Some(Shl { x, y }) => self._8xye(x, y),
+            Some(SneReg { x, y }) => skip_instruction = self._9xy0(x, y),
+            Some(LdI(nnn)) => self._annn(nnn),
+            Some(JpV0(nnn)) => {
+                self._bnnn(nnn);
                 update_pc = false;
             }
-            (0xc, _, _, _) => self._cxnn(opcode.1, nn(opcode)),
-            (0xd, _, _, _) => self._dxyn(opcode.1, opcode.2, opcode.3),
-            (0xe, _, 0x9, _) => skip_instruction = self._ex9e(opcode.1),
-            (0xe, _, 0xa, _) => skip_instruction = self._exa1(opcode.1),
-            (0xf, _, 0x0, 0x7) => self._fx07(opcode.1),
-            (0xf, _, 0x0, 0xa) => self._fx0a(opcode.1),
-            (0xf, _, 0x1, 0x5) => self._fx15(opcode.1),
-            (0xf, _, 0x1, 0x8) => self._fx18(opcode.1),
-            (0xf, _, 0x1, 0xe) => self._fx1e(opcode.1),
-            (0xf, _, 0x2, _) => self._fx29(opcode.1),
-            (0xf, _, 0x3, _) => self._fx33(opcode.1),
-            (0xf, _, 0x5, _) => self._fx55(opcode.1),
-            (0xf, _, 0x6, _) => self._fx65(opcode.1),
-            _ => {}
+            Some(Rnd { x, nn }) => self._cxnn(x, nn),
+            Some(Drw { x, y, n }) => self._dxyn(x, y, n),
+            Some(Skp { x }) => skip_instruction = self._ex9e(x)?,
+            Some(Sknp { x }) => skip_instruction = self._exa1(x)?,
+            Some(LdILong) => {
+                self._f000();
+                pc_increment = 4;
+            }
+            Some(Plane(n)) => self.state.planes = n,
+            Some(Audio) => self._f002(),
+            Some(LdVxDt { x }) => self._fx07(x),
+            Some(LdVxK { x }) => self._fx0a(x)?,
+            Some(LdDtVx { x }) => self._fx15(x),
+            Some(LdStVx { x }) => self._fx18(x),
+            Some(AddI { x }) => self._fx1e(x),
+            Some(LdF { x }) => self._fx29(x),
+            Some(LdHf { x }) => self._fx30(x),
+            Some(Pitch { x }) => self.state.pitch = self.state.registers[x as usize],
+            Some(LdB { x }) => self._fx33(x),
+            Some(LdIVx { x }) => self._fx55(x),
+            Some(LdVxI { x }) => self._fx65(x),
+            Some(SaveFlags { x }) => self._fx75(x),
+            Some(LoadFlags { x }) => self._fx85(x),
+            None => {}
         }
         if skip_instruction {
-            pc_increment += 2;
+            pc_increment += self.instruction_length(self.state.program_counter + 2);
         }
         if update_pc {
-            self.program_counter += pc_increment;
+            self.state.program_counter += pc_increment;
+        }
+        self.update_timers();
+        Ok(())
+    }
+
+    fn execute_mega(&mut self, instruction: MegaInstruction) {
+        let index = self.index_address();
+        let previous = self.screen_area();
+        let Some(megachip) = self.state.megachip.as_mut() else {
+            return;
+        };
+        self.state.program_counter += 2;
+        match instruction {
+            MegaInstruction::On | MegaInstruction::Off => {
+                megachip.set_enabled(instruction == MegaInstruction::On);
+                megachip.clear(&mut self.memory);
+                self.display.fill_solid(&previous, Rgb565::BLACK).ok();
+                self._00e0();
+            }
+            MegaInstruction::LdIFar(high) => {
+                let mut low = [0; 2];
+                self.memory
+                    .read_slice(self.state.program_counter as usize, &mut low);
+                megachip.set_index_high(high);
+                self.state.index = u16::from_be_bytes(low);
+                self.state.program_counter += 2;
+            }
+            _ => megachip.execute(instruction, index, &mut self.memory),
         }
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+    }
+
+    fn execute_chip8x(&mut self, instruction: Chip8XInstruction) -> Result<(), Error> {
+        use Chip8XInstruction::*;
+        let mut skip_instruction = false;
+        match instruction {
+            CycleBackground => {
+                if let Some(chip8x) = self.state.chip8x.as_mut() {
+                    chip8x.cycle_background();
+                }
+                let (width, height) = self.screen_size();
+                self.redraw_region((0, width), (0, height));
+            }
+            AddNibbles { x, y } => {
+                let (vx, vy) = (
+                    self.state.registers[x as usize],
+                    self.state.registers[y as usize],
+                );
+                self.state.registers[x as usize] =
+                    (vx.wrapping_add(vy & 0xf0) & 0x70) | ((vx & 0xf).wrapping_add(vy & 0xf) & 0x7);
+            }
+            ZoneColor { x, y } => self.set_zone_color(x, y, None),
+            LineColor { x, y, n } => self.set_zone_color(x, y, Some(n)),
+            Skp2 { x } | Sknp2 { x } => {
+                let keys = match self.second_keypad.as_mut() {
+                    Some(keypad) => keypad.scan(&mut self.delay).map_err(|_| Error::KeyPad {
+                        address: self.state.program_counter,
+                    })?,
+                    None => 0,
+                };
+                let held = keys & (1 << (self.state.registers[x as usize] & 0xf)) != 0;
+                skip_instruction = held == matches!(instruction, Skp2 { .. });
+            }
         }
+        self.state.program_counter += if skip_instruction { 4 } else { 2 };
+        Ok(())
+    }
+
+    /// bxy0 and bxyn, colors zones and redraws them
+    fn set_zone_color(&mut self, x: Nibble, y: Nibble, lines: Option<Nibble>) {
+        let columns = self.state.registers[x as usize];
+        let rows = self.state.registers[(x as usize + 1) % NUM_REGISTERS];
+        let color = self.state.registers[y as usize];
+        let Some(chip8x) = self.state.chip8x.as_mut() else {
+            return;
+        };
+        let (columns, lines) = chip8x.set_color(columns, rows, lines, color);
+        let (width, height) = self.screen_size();
+        self.redraw_region(
+            (
+                columns.0 * width / chip8x::ZONE_COLUMNS,
+                columns.1 * width / chip8x::ZONE_COLUMNS,
+            ),
+            (
+                lines.0 * height / chip8x::ZONE_ROWS,
+                lines.1 * height / chip8x::ZONE_ROWS,
+            ),
+        );
+    }
 
-        if self.sound_timer > 0 {
-            if self.sound_timer == 1 {}
-            self.sound_timer -= 1;
+    /// Returns the display color of a pixel with the given planes set
+    fn pixel_color(&self, x: usize, y: usize, planes: u8) -> Rgb565 {
+        match self.state.chip8x.as_ref() {
+            Some(chip8x) => chip8x.color(x, y, planes != 0, self.screen_size()),
+            None => self.framebuffer.colors()[(planes & 3) as usize],
         }
     }
 
+    /// Draws the pixels in the given column and line ranges
+    fn redraw_region(&mut self, columns: (usize, usize), lines: (usize, usize)) {
+        let scale = self.render_scale();
+        for y in lines.0..lines.1 {
+            for x in columns.0..columns.1 {
+                let planes = self.pixel(x, y);
+                let point = Point::new(
+                    (self.state.padding + x * scale.0) as i32,
+                    (y * scale.1) as i32,
+                );
+                let rect = Rectangle::new(point, Size::new(scale.0 as u32, scale.1 as u32));
+                let color = self.pixel_color(x, y, planes);
+                self.display.fill_solid(&rect, color).ok();
+            }
+        }
+    }
+
+    /// Returns the address in the index register, 24 bits in MEGA-CHIP mode
+    fn index_address(&self) -> usize {
+        match self.state.megachip {
+            Some(megachip) if megachip.enabled() => {
+                ((megachip.index_high() as usize) << 16) | self.state.index as usize
+            }
+            _ => self.state.index as usize,
+        }
+    }
+
+    /// Sets the index register to an address from `index_address`
+    fn set_index_address(&mut self, address: usize) {
+        if let Some(megachip) = self.state.megachip.as_mut() {
+            if megachip.enabled() {
+                megachip.set_index_high((address >> 16) as u8);
+            }
+        }
+        self.state.index = address as u16;
+    }
+
+    /// Returns whether MEGA-CHIP mode is on
+    fn mega_enabled(&self) -> bool {
+        matches!(self.state.megachip, Some(megachip) if megachip.enabled())
+    }
+
+    fn update_timers(&mut self) {
+        if self.state.delay_timer > 0 {
+            self.state.delay_timer -= 1;
+        }
+
+        if self.state.sound_timer > 0 {
+            self.state.sound_timer -= 1;
+        }
+    }
+
+    /// Returns the width and height of the chip8 screen
+    fn screen_size(&self) -> (usize, usize) {
+        match self.mega_enabled() {
+            true => (MEGA_WIDTH, MEGA_HEIGHT),
+            false => (
+                self.state.resolution.width(),
+                self.state.resolution.height(),
+            ),
+        }
+    }
+
+    /// Returns the scale adjusted for the current resolution
+    fn render_scale(&self) -> (usize, usize) {
+        let (width, height) = self.screen_size();
+        (
+            (self.state.scale.0 * CHIP8_WIDTH / width).max(1),
+            (self.state.scale.1 * CHIP8_HEIGHT / height).max(1),
+        )
+    }
+
+    /// Returns the part of the display the chip8 screen is drawn in
+    fn screen_area(&self) -> Rectangle {
+        let (width, height) = self.screen_size();
+        let scale = self.render_scale();
+        Rectangle::new(
+            Point::new(self.state.padding as i32, 0),
+            Size::new((width * scale.0) as u32, (height * scale.1) as u32),
+        )
+    }
+
+    /// Returns where the display starts in memory with [`MemoryLayout::Vip`]
+    fn framebuffer_address(&self) -> usize {
+        VIP_FRAMEBUFFER_END - self.state.resolution.width() * self.state.resolution.height() / 8
+    }
+
     /// 00e0 Clear screen
     fn _00e0(&mut self) {
-        let rect = &Rectangle::new(Point::new(0, 0), self.display.size());
-        if self.display.fill_solid(rect, Rgb565::BLACK).is_err() {}
+        if let Some(megachip) = self.state.megachip.filter(|megachip| megachip.enabled()) {
+            return self.mega_update(megachip);
+        }
+        let mask = self.plane_mask();
+        if self.state.instruction_set == InstructionSet::XoChip
+            && self.state.memory_layout == MemoryLayout::Separate
+            && mask & 3 != 3
+        {
+            // XO-CHIP clears only the selected planes
+            self.framebuffer.clear(mask);
+            let (width, height) = self.screen_size();
+            return self.redraw_region((0, width), (0, height));
+        }
+        self.clear_display();
+        match self.state.memory_layout {
+            MemoryLayout::Separate => self.framebuffer.clear(!0),
+            MemoryLayout::Vip => {
+                let start = self.framebuffer_address();
+                for address in start..VIP_FRAMEBUFFER_END {
+                    self.memory.write(address, 0);
+                }
+            }
+        }
     }
 
     /// 00ee return
-    fn _00ee(&mut self) {
-        self.program_counter = self.stack[self.stack_pointer];
-        self.stack_pointer -= 1;
+    fn _00ee(&mut self) -> Result<(), Error> {
+        let underflow = Error::StackUnderflow {
+            address: self.state.program_counter,
+        };
+        if self.state.stack_pointer == 0 {
+            return Err(underflow);
+        }
+        self.state.program_counter = match self.state.memory_layout {
+            MemoryLayout::Separate => self.state.stack[self.state.stack_pointer],
+            MemoryLayout::Vip => {
+                let mut address = [0; 2];
+                self.memory.read_slice(self.vip_stack_entry(), &mut address);
+                // The VIP stores the return address, execution
+                // continues two bytes past what is kept here
+                u16::from_be_bytes(address)
+                    .checked_sub(2)
+                    .ok_or(underflow)?
+            }
+        };
+        self.state.stack_pointer -= 1;
+        Ok(())
     }
 
     /// 1nnn jump
     fn _1nnn(&mut self, nnn: u16) {
-        self.program_counter = nnn;
+        self.state.program_counter = nnn;
     }
 
     /// 2nnn
-    fn _2nnn(&mut self, nnn: u16) {
-        self.stack_pointer += 1;
-        self.stack[self.stack_pointer] = self.program_counter;
-        self.program_counter = nnn;
+    fn _2nnn(&mut self, nnn: u16) -> Result<(), Error> {
+        if self.state.stack_pointer + 1 >= STACK_SIZE {
+            return Err(Error::StackOverflow {
+                address: self.state.program_counter,
+            });
+        }
+        self.state.stack_pointer += 1;
+        match self.state.memory_layout {
+            MemoryLayout::Separate => {
+                self.state.stack[self.state.stack_pointer] = self.state.program_counter
+            }
+            MemoryLayout::Vip => {
+                let address = self.state.program_counter + 2;
+                self.memory
+                    .write_slice(self.vip_stack_entry(), &address.to_be_bytes());
+            }
+        }
+        self.state.program_counter = nnn;
+        Ok(())
+    }
+
+    /// Returns where the top of the stack is kept with [`MemoryLayout::Vip`]
+    ///
+    /// The stack stays at the start of the reserved area whatever the
+    /// resolution, so a HIRES display cannot move it
+    fn vip_stack_entry(&self) -> usize {
+        let reserved = self.state.memory_map.reserved_start.min(VIP_RESERVED_START);
+        reserved + VIP_STACK_OFFSET - 2 * self.state.stack_pointer
     }
 
     /// 3xnn
     fn _3xnn(&self, x: Nibble, nn: u8) -> bool {
-        self.registers[x as usize] == nn
+        self.state.registers[x as usize] == nn
     }
 
     /// 4xnn
     fn _4xnn(&self, x: Nibble, nn: u8) -> bool {
-        self.registers[x as usize] != nn
+        self.state.registers[x as usize] != nn
     }
 
     /// 5xy0
     fn _5xy0(&self, x: Nibble, y: Nibble) -> bool {
-        self.registers[x as usize] == self.registers[y as usize]
+        self.state.registers[x as usize] == self.state.registers[y as usize]
     }
 
     /// 6xnn Set register vx to nn
     fn _6xnn(&mut self, x: Nibble, nn: u8) {
-        self.registers[x as usize] = nn;
+        self.state.registers[x as usize] = nn;
     }
 
     /// 7xnn Add nn to register vx
     fn _7xnn(&mut self, x: Nibble, nn: u8) {
-        let (new_x, over) = self.registers[x as usize].overflowing_add(nn);
-        self.registers[x as usize] = new_x;
+        let (new_x, over) = self.state.registers[x as usize].overflowing_add(nn);
+        self.state.registers[x as usize] = new_x;
     }
 
     /// 8xy0
     fn _8xy0(&mut self, x: Nibble, y: Nibble) {
-        self.registers[x as usize] = self.registers[y as usize];
+        self.state.registers[x as usize] = self.state.registers[y as usize];
     }
 
     /// 8xy1
     fn _8xy1(&mut self, x: Nibble, y: Nibble) {
-        self.registers[x as usize] |= self.registers[y as usize];
+        self.state.registers[x as usize] |= self.state.registers[y as usize];
+        self.reset_vf();
     }
 
     /// 8xy2
     fn _8xy2(&mut self, x: Nibble, y: Nibble) {
-        self.registers[x as usize] &= self.registers[y as usize];
+        self.state.registers[x as usize] &= self.state.registers[y as usize];
+        self.reset_vf();
     }
 
     /// 8xy3
     fn _8xy3(&mut self, x: Nibble, y: Nibble) {
-        self.registers[x as usize] ^= self.registers[y as usize];
+        self.state.registers[x as usize] ^= self.state.registers[y as usize];
+        self.reset_vf();
+    }
+
+    /// Clears vf after a logic instruction with the `vf_reset` quirk
+    fn reset_vf(&mut self) {
+        if self.state.quirks.vf_reset {
+            self.state.registers[0xf] = 0;
+        }
+    }
+
+    /// Returns the register shifted by `8xy6` and `8xye`
+    fn shift_source(&self, x: Nibble, y: Nibble) -> u8 {
+        match self.state.quirks.shift_vy {
+            true => self.state.registers[y as usize],
+            false => self.state.registers[x as usize],
+        }
     }
 
     /// 8xy4
     fn _8xy4(&mut self, x: Nibble, y: Nibble) {
-        let (new_x, over) = self.registers[x as usize].overflowing_add(self.registers[y as usize]);
-        self.registers[x as usize] = new_x;
-        self.registers[0xf] = if over { 1 } else { 0 };
+        let (new_x, over) =
+            self.state.registers[x as usize].overflowing_add(self.state.registers[y as usize]);
+        self.state.registers[x as usize] = new_x;
+        self.state.registers[0xf] = if over { 1 } else { 0 };
     }
 
     /// 8xy5
     fn _8xy5(&mut self, x: Nibble, y: Nibble) {
-        let (new_x, over) = self.registers[x as usize].overflowing_sub(self.registers[y as usize]);
-        self.registers[x as usize] = new_x;
-        self.registers[0xf] = if over { 1 } else { 0 };
+        let (new_x, over) =
+            self.state.registers[x as usize].overflowing_sub(self.state.registers[y as usize]);
+        self.state.registers[x as usize] = new_x;
+        self.state.registers[0xf] = if over { 0 } else { 1 };
     }
 
     /// 8xy6
     fn _8xy6(&mut self, x: Nibble, y: Nibble) {
-        let bit: u8 = self.registers[x as usize] & 1;
-        self.registers[x as usize] >>= 1;
-        self.registers[0xf] = bit;
+        let value = self.shift_source(x, y);
+        self.state.registers[x as usize] = value >> 1;
+        self.state.registers[0xf] = value & 1;
     }
 
     /// 8xy7
     fn _8xy7(&mut self, x: Nibble, y: Nibble) {
-        let (new_y, over) = self.registers[y as usize].overflowing_sub(self.registers[x as usize]);
-        self.registers[y as usize] = new_y;
-        self.registers[0xf] = if over { 1 } else { 0 };
+        let (new_x, over) =
+            self.state.registers[y as usize].overflowing_sub(self.state.registers[x as usize]);
+        self.state.registers[x as usize] = new_x;
+        self.state.registers[0xf] = if over { 0 } else { 1 };
     }
 
     /// 8xye
     fn _8xye(&mut self, x: Nibble, y: Nibble) {
-        let bit: u8 = (self.registers[x as usize] & 8) >> 3;
-        self.registers[x as usize] <<= 1;
-        self.registers[0xf] = bit;
+        let value = self.shift_source(x, y);
+        self.state.registers[x as usize] = value << 1;
+        self.state.registers[0xf] = value >> 7;
     }
 
     /// 9xy0
     fn _9xy0(&self, x: Nibble, y: Nibble) -> bool {
-        self.registers[x as usize] != self.registers[y as usize]
+        self.state.registers[x as usize] != self.state.registers[y as usize]
     }
 
     /// annn set index register i
     fn _annn(&mut self, nnn: u16) {
-        self.index = nnn;
+        self.set_index_address(nnn as usize);
     }
 
-    /// bnnn Jump with offset
+    /// bnnn Jump with offset, bxnn adds vx with the `jump_vx` quirk
     fn _bnnn(&mut self, nnn: u16) {
-        self.program_counter = nnn + self.registers[0] as u16;
+        let register = match self.state.quirks.jump_vx {
+            true => (nnn >> 8) as usize,
+            false => 0,
+        };
+        self.state.program_counter = nnn + self.state.registers[register] as u16;
     }
 
     /// cxnn Random number
     fn _cxnn(&mut self, x: Nibble, nn: u8) {
-        let rand_num: u8 = (self.rng.next_u32().to_le_bytes()[3]) as u8;
-        self.registers[x as usize] = rand_num & nn;
+        let rand_num: u8 = self.rng.next_u32().to_le_bytes()[3];
+        self.state.registers[x as usize] = rand_num & nn;
+    }
+
+    /// 00e0 in MEGA-CHIP mode, draws the framebuffer then clears it
+    fn mega_update(&mut self, megachip: MegaChip) {
+        self.mega_draw(megachip);
+        megachip.clear(&mut self.memory);
+    }
+
+    /// Draws the MEGA-CHIP framebuffer through the palette
+    fn mega_draw(&mut self, megachip: MegaChip) {
+        let area = self.screen_area();
+        let scale = self.render_scale();
+        let width = area.size.width as usize;
+        let memory = &mut self.memory;
+        let colors = (0..area.size.height as usize)
+            .flat_map(|y| (0..width).map(move |x| (x / scale.0, y / scale.1)));
+        let colors = colors.map(|(x, y)| megachip.color(y * MEGA_WIDTH + x, &mut *memory));
+        self.display.fill_contiguous(&area, colors).ok();
     }
 
     /// dxyn draw screen
     fn _dxyn(&mut self, x: Nibble, y: Nibble, n: Nibble) {
+        if let Some(megachip) = self.state.megachip.filter(|megachip| megachip.enabled()) {
+            let index = self.index_address();
+            let (x, y) = (
+                self.state.registers[x as usize],
+                self.state.registers[y as usize],
+            );
+            let collision = megachip.draw(x as usize, y as usize, index, &mut self.memory);
+            self.state.registers[0xf] = collision as u8;
+            return;
+        }
+        let (width, height) = (
+            self.state.resolution.width(),
+            self.state.resolution.height(),
+        );
         let coords: (usize, usize) = (
-            (self.registers[x as usize] % (CHIP8_WIDTH as u8)) as usize,
-            (self.registers[y as usize] % (CHIP8_HEIGHT as u8)) as usize,
+            self.state.registers[x as usize] as usize % width,
+            self.state.registers[y as usize] as usize % height,
         );
-        self.registers[0xf] = 0;
-        for i in 0..n as usize {
-            let sprite = self.memory[(self.index as usize + i)];
-            for j in 0..u8::BITS as usize {
-                if sprite & (0x80 >> j) != 0 {
-                    let point = Point::new(
-                        (self.padding + (coords.0 + j) * self.scale.0) as i32,
-                        ((coords.1 + i) * self.scale.1) as i32,
-                    );
-                    let rect =
-                        &Rectangle::new(point, Size::new(self.scale.0 as u32, self.scale.1 as u32));
-                    if coords.0 + j < self.pixels.len() && coords.1 + i < self.pixels[0].len() {
-                        if self.pixels[coords.0 + j][coords.1 + i] {
-                            if self.display.fill_solid(rect, Rgb565::BLACK).is_err() {}
-                            self.pixels[coords.0 + j][coords.1 + i] = false;
-                            self.registers[0xf] = 1;
-                        } else {
-                            if self.display.fill_solid(rect, Rgb565::WHITE).is_err() {}
-                            self.pixels[coords.0 + j][coords.1 + i] = true;
-                        }
-                    }
+        let (columns, rows) = match (n, self.state.instruction_set) {
+            (0, InstructionSet::SuperChip | InstructionSet::XoChip) => (16, 16),
+            (n, _) => (8, n as usize),
+        };
+        let row_bytes = columns / 8;
+        let planes = self.plane_mask();
+        let mut address = self.index_address();
+        // One bit per sprite row that turned a pixel off
+        let mut collisions: u16 = 0;
+        for plane in (0..PLANES).filter(|plane| planes & (1 << plane) != 0) {
+            for i in 0..rows {
+                let mut sprite = [0; 2];
+                self.memory.read_slice(address, &mut sprite[..row_bytes]);
+                address += row_bytes;
+                let mut y = coords.1 + i;
+                if self.state.quirks.wrap {
+                    y %= height;
+                } else if y >= height {
+                    continue;
+                }
+                let sprite = u16::from_be_bytes(sprite);
+                let sprite = framebuffer::place_sprite(
+                    sprite,
+                    columns,
+                    coords.0,
+                    width,
+                    self.state.quirks.wrap,
+                );
+                let row = self.row(plane, y);
+                if row & sprite != 0 {
+                    collisions |= 1 << i;
                 }
+                self.set_row(plane, y, row ^ sprite);
+                self.draw_pixels(y, sprite);
+            }
+        }
+        self.state.registers[0xf] = match self.state.quirks.row_collisions
+            && self.state.resolution != Resolution::Standard
+        {
+            true => collisions.count_ones() as u8,
+            false => (collisions != 0) as u8,
+        };
+    }
+
+    /// Draws the pixels of line `y` set in `pixels` to the display
+    fn draw_pixels(&mut self, y: usize, mut pixels: Row) {
+        let scale = self.render_scale();
+        let size = Size::new(scale.0 as u32, scale.1 as u32);
+        while pixels != 0 {
+            let x = pixels.leading_zeros() as usize;
+            pixels &= !(1 << (MAX_WIDTH - 1 - x));
+            let point = Point::new(
+                (self.state.padding + x * scale.0) as i32,
+                (y * scale.1) as i32,
+            );
+            let planes = self.pixel(x, y);
+            let color = self.pixel_color(x, y, planes);
+            self.display
+                .fill_solid(&Rectangle::new(point, size), color)
+                .ok();
+        }
+    }
+
+    /// Returns the planes set at (x, y), one bit per plane
+    fn pixel(&mut self, x: usize, y: usize) -> u8 {
+        match self.state.memory_layout {
+            MemoryLayout::Separate => self.framebuffer.get(x, y),
+            MemoryLayout::Vip => {
+                let address =
+                    self.framebuffer_address() + (y * self.state.resolution.width() + x) / 8;
+                let byte = self.memory.read(address);
+                (byte & (0x80 >> (x % 8)) != 0) as u8
+            }
+        }
+    }
+
+    /// Returns a row of a plane, the VIP display only has the first plane
+    fn row(&mut self, plane: usize, y: usize) -> Row {
+        match self.state.memory_layout {
+            MemoryLayout::Separate => self.framebuffer.row(plane, y),
+            MemoryLayout::Vip if plane == 0 => {
+                let bytes = self.state.resolution.width() / 8;
+                let mut row = [0; MAX_WIDTH / 8];
+                let address = self.framebuffer_address() + y * bytes;
+                self.memory.read_slice(address, &mut row[..bytes]);
+                Row::from_be_bytes(row)
+            }
+            MemoryLayout::Vip => 0,
+        }
+    }
+
+    /// Replaces a row of a plane
+    fn set_row(&mut self, plane: usize, y: usize, row: Row) {
+        match self.state.memory_layout {
+            MemoryLayout::Separate => self.framebuffer.set_row(plane, y, row),
+            MemoryLayout::Vip if plane == 0 => {
+                let bytes = self.state.resolution.width() / 8;
+                let address = self.framebuffer_address() + y * bytes;
+                self.memory
+                    .write_slice(address, &row.to_be_bytes()[..bytes]);
+            }
+            MemoryLayout::Vip => {}
+        }
+    }
+
+    /// Scans the keypad and remembers the highest key held down
+    fn scan_keys(&mut self) -> Result<u16, Error> {
+        let keys = self
+            .keypad
+            .scan(&mut self.delay)
+            .map_err(|_| Error::KeyPad {
+                address: self.state.program_counter,
+            })?;
+        if keys != 0 {
+            self.state.last_key = (15 - keys.leading_zeros()) as u8;
+        }
+        if let Some(keypad) = self.state.virtual_keypad {
+            if keys != self.state.drawn_keys {
+                keypad.draw(&mut self.display, keys).ok();
+                self.state.drawn_keys = keys;
             }
         }
+        Ok(keys)
     }
 
     /// ex9e
-    fn _ex9e(&mut self, x: Nibble) -> bool {
-        let key = self.keypad.get(&mut self.delay);
-        self.last_key = key.1;
-        key.0 && key.1 == self.registers[x as usize]
+    fn _ex9e(&mut self, x: Nibble) -> Result<bool, Error> {
+        let keys = self.scan_keys()?;
+        Ok(keys & (1 << (self.state.registers[x as usize] & 0xf)) != 0)
     }
 
     /// exa1
-    fn _exa1(&mut self, x: Nibble) -> bool {
-        let key = self.keypad.get(&mut self.delay);
-        self.last_key = key.1;
-        key.0 && key.1 != self.registers[x as usize]
+    fn _exa1(&mut self, x: Nibble) -> Result<bool, Error> {
+        let keys = self.scan_keys()?;
+        Ok(keys & (1 << (self.state.registers[x as usize] & 0xf)) == 0)
     }
 
     /// fx07
     fn _fx07(&mut self, x: Nibble) {
-        self.registers[x as usize] = self.delay_timer;
+        self.state.registers[x as usize] = self.state.delay_timer;
     }
 
     /// fx0a
-    fn _fx0a(&mut self, x: Nibble) {
-        let mut key = (false, 0);
-        while !key.0 {
-            key = self.keypad.get(&mut self.delay);
-            self.last_key = key.1;
-        }
-        self.registers[x as usize] = key.1;
+    fn _fx0a(&mut self, x: Nibble) -> Result<(), Error> {
+        while self.scan_keys()? == 0 {}
+        self.state.registers[x as usize] = self.state.last_key;
+        Ok(())
     }
 
     /// fx15
     fn _fx15(&mut self, x: Nibble) {
-        self.delay_timer = self.registers[x as usize];
+        self.state.delay_timer = self.state.registers[x as usize];
     }
 
     /// fx18
     fn _fx18(&mut self, x: Nibble) {
-        self.sound_timer = self.registers[x as usize];
+        self.state.sound_timer = self.state.registers[x as usize];
     }
 
     /// fx1e
     fn _fx1e(&mut self, x: Nibble) {
-        if ((self.index + x as u16) as usize) < RAM_SIZE {
-            self.index += x as u16;
-        } else {
-            self.index += x as u16;
-            self.registers[0xf] = 1;
+        let address = self.index_address() + self.state.registers[x as usize] as usize;
+        if self.state.quirks.index_overflow && address >= self.ram_size() {
+            self.state.registers[0xf] = 1;
         }
+        self.set_index_address(address);
     }
 
-    /// fx29
+    /// fx29 Point index at the small font glyph for vx
     fn _fx29(&mut self, x: Nibble) {
-        let character = self.registers[x as usize];
-        self.index = self.memory[(FONT_START + (character * 5) as usize) as usize] as u16;
+        let character = (self.state.registers[x as usize] & 0xf) as usize;
+        let height = self.state.font_height as usize;
+        self.set_index_address(self.state.font_address as usize + character * height);
+    }
+
+    /// fx30 Point index at the large font glyph for vx
+    fn _fx30(&mut self, x: Nibble) {
+        let character = (self.state.registers[x as usize] & 0xf) as usize;
+        let height = self.state.big_font_height as usize;
+        self.set_index_address(self.state.big_font_address as usize + character * height);
     }
 
     /// fx33
     fn _fx33(&mut self, x: Nibble) {
-        let num = self.registers[x as usize];
+        let num = self.state.registers[x as usize];
         let digits = (num / 100, (num % 100) / 10, num % 10);
-        self.memory[self.index as usize] = digits.0;
-        self.memory[(self.index + 1) as usize] = digits.1;
-        self.memory[(self.index + 2) as usize] = digits.2;
+        self.memory
+            .write_slice(self.index_address(), &[digits.0, digits.1, digits.2]);
     }
 
     /// fx55
     fn _fx55(&mut self, x: Nibble) {
-        for i in 0..(x + 1) as usize {
-            self.memory[self.index as usize + i] = self.registers[i];
-        }
+        let count = (x + 1) as usize;
+        self.memory
+            .write_slice(self.index_address(), &self.state.registers[..count]);
+        self.increment_index(x);
     }
 
     /// fx65
     fn _fx65(&mut self, x: Nibble) {
-        for i in 0..(x + 1) as usize {
-            self.registers[i] = self.memory[self.index as usize + i];
+        let count = (x + 1) as usize;
+        self.memory
+            .read_slice(self.index_address(), &mut self.state.registers[..count]);
+        self.increment_index(x);
+    }
+
+    /// Moves the index past the registers stored or loaded
+    /// by `fx55` and `fx65` as the quirks say
+    fn increment_index(&mut self, x: Nibble) {
+        let increment = match self.state.quirks.index_increment {
+            IndexIncrement::None => return,
+            IndexIncrement::X => x as usize,
+            IndexIncrement::XPlusOne => x as usize + 1,
+        };
+        self.set_index_address(self.index_address() + increment);
+    }
+
+    /// fx75 Save v0 to vx to the flag registers
+    fn _fx75(&mut self, x: Nibble) {
+        let count = (x + 1) as usize;
+        self.state.flags[..count].copy_from_slice(&self.state.registers[..count]);
+    }
+
+    /// fx85 Load v0 to vx from the flag registers
+    fn _fx85(&mut self, x: Nibble) {
+        let count = (x + 1) as usize;
+        self.state.registers[..count].copy_from_slice(&self.state.flags[..count]);
+    }
+
+    /// 5xy2 Store vx to vy at index, in reverse when x is above y
+    fn _5xy2(&mut self, x: Nibble, y: Nibble) {
+        let index = self.index_address();
+        for (offset, register) in Self::register_range(x, y).enumerate() {
+            self.memory
+                .write(index + offset, self.state.registers[register]);
+        }
+    }
+
+    /// 5xy3 Load vx to vy from index, in reverse when x is above y
+    fn _5xy3(&mut self, x: Nibble, y: Nibble) {
+        let index = self.index_address();
+        for (offset, register) in Self::register_range(x, y).enumerate() {
+            self.state.registers[register] = self.memory.read(index + offset);
         }
     }
+
+    /// Returns the registers from vx to vy in order
+    fn register_range(x: Nibble, y: Nibble) -> impl Iterator<Item = usize> {
+        let (x, y) = (x as usize, y as usize);
+        (0..=x.abs_diff(y)).map(move |i| if x <= y { x + i } else { x - i })
+    }
+
+    /// f000 nnnn Set index to the following 16 bit address
+    fn _f000(&mut self) {
+        let mut address = [0; 2];
+        self.memory
+            .read_slice(self.state.program_counter as usize + 2, &mut address);
+        self.set_index_address(u16::from_be_bytes(address) as usize);
+    }
+
+    /// f002 Load the audio pattern from index
+    fn _f002(&mut self) {
+        self.memory
+            .read_slice(self.index_address(), &mut self.state.audio_pattern);
+    }
 }
@@ -0,0 +1,378 @@
+use core::fmt;
+
+use embedded_graphics::pixelcolor::Rgb565;
+
+use crate::bus::Bus;
+use crate::Opcode;
+
+/// Width of the MEGA-CHIP screen
+pub const MEGA_WIDTH: usize = 256;
+/// Height of the MEGA-CHIP screen
+pub const MEGA_HEIGHT: usize = 192;
+/// Bytes of memory used by the indexed framebuffer
+pub const FRAMEBUFFER_SIZE: usize = MEGA_WIDTH * MEGA_HEIGHT;
+/// Bytes of memory used by the palette, 256 ARGB colors
+pub const PALETTE_SIZE: usize = 256 * 4;
+/// Bytes before the samples of a digitized sound
+const SAMPLE_HEADER: usize = 6;
+
+/// How sprite pixels are combined with the screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Blend {
+    /// Sprite pixels replace the screen
+    Normal,
+    /// 25% sprite, 75% screen
+    Percent25,
+    /// 50% sprite, 50% screen
+    Percent50,
+    /// Sprite and screen colors are added
+    Add,
+    /// Sprite and screen colors are multiplied
+    Multiply,
+}
+
+/// A MEGA-CHIP instruction
+///
+/// These only exist in MEGA-CHIP mode, otherwise they are `0nnn` calls
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MegaInstruction {
+    /// 0010 Leave MEGA-CHIP mode
+    Off,
+    /// 0011 Enter MEGA-CHIP mode
+    On,
+    /// 00bn Scroll the screen up n lines
+    ScrollUp(u8),
+    /// 01nn nnnn Set index to a 24 bit address, this holds the high byte
+    /// and the low 16 bits are in the following two bytes
+    LdIFar(u8),
+    /// 02nn Load nn ARGB colors from index into palette entries 1 to nn
+    LdPalette(u8),
+    /// 03nn Set the sprite width, 0 is 256
+    SpriteWidth(u8),
+    /// 04nn Set the sprite height, 0 is 256
+    SpriteHeight(u8),
+    /// 05nn Set the screen alpha
+    Alpha(u8),
+    /// 060n Play the digitized sound at index, looping if n is 0
+    PlaySound { looping: bool },
+    /// 0700 Stop the digitized sound
+    StopSound,
+    /// 080n Set the blend mode
+    BlendMode(u8),
+    /// 09nn Set the palette entry that counts as a collision,
+    /// drawing over the transparent entry 0 never collides
+    Collision(u8),
+}
+
+impl MegaInstruction {
+    /// Decodes an opcode, returning `None` if it is not a MEGA-CHIP instruction
+    pub fn decode(opcode: Opcode) -> Option<MegaInstruction> {
+        use MegaInstruction::*;
+        let (high, nn) = opcode;
+        Some(match (high, nn >> 4, nn & 0xf) {
+            (0x00, 0x1, 0x0) => Off,
+            (0x00, 0x1, 0x1) => On,
+            (0x00, 0xb, n) => ScrollUp(n),
+            (0x01, _, _) => LdIFar(nn),
+            (0x02, _, _) => LdPalette(nn),
+            (0x03, _, _) => SpriteWidth(nn),
+            (0x04, _, _) => SpriteHeight(nn),
+            (0x05, _, _) => Alpha(nn),
+            (0x06, 0x0, n) if n < 2 => PlaySound { looping: n == 0 },
+            (0x07, 0x0, 0x0) => StopSound,
+            (0x08, 0x0, n) if n < 5 => BlendMode(n),
+            (0x09, _, _) => Collision(nn),
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for MegaInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use MegaInstruction::*;
+        match *self {
+            Off => write!(f, "MEGAOFF"),
+            On => write!(f, "MEGAON"),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            LdIFar(nn) => write!(f, "LDHI 0x{:02X}....", nn),
+            LdPalette(nn) => write!(f, "LDPAL {}", nn),
+            SpriteWidth(nn) => write!(f, "SPRW {}", nn),
+            SpriteHeight(nn) => write!(f, "SPRH {}", nn),
+            Alpha(nn) => write!(f, "ALPHA 0x{:02X}", nn),
+            PlaySound { looping } => write!(f, "DIGISND {}", !looping as u8),
+            StopSound => write!(f, "STOPSND"),
+            BlendMode(n) => write!(f, "BMODE {}", n),
+            Collision(nn) => write!(f, "CCOL 0x{:02X}", nn),
+        }
+    }
+}
+
+/// A digitized sound being played
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    start: usize,
+    length: usize,
+    rate: u16,
+    /// Position in samples as 48.16 fixed point, so sounds
+    /// longer than 65536 samples can be played
+    position: u64,
+    looping: bool,
+}
+
+/// MEGA-CHIP support
+///
+/// MEGA-CHIP mode draws on a 256x192 screen with colored sprites, one
+/// palette index per byte with index 0 transparent. Both the framebuffer
+/// and the palette live in chip8 memory at the addresses given to `new`,
+/// so memory must be large enough to hold them as well as the program,
+/// [`FRAMEBUFFER_SIZE`] and [`PALETTE_SIZE`] bytes respectively.
+///
+/// Sprites are drawn into the framebuffer and the screen is only updated
+/// by `00e0`, which draws the framebuffer through the palette and screen
+/// alpha then clears it, as MEGA-CHIP programs expect. The framebuffer
+/// holds palette indexes, so with a [`Blend`] other than `Normal` a pixel
+/// takes the palette entry closest to the blended color.
+///
+/// Digitized sound is played by calling `next_sample` on the Chip8
+/// at the output sample rate.
+#[derive(Clone, Copy, Debug)]
+pub struct MegaChip {
+    framebuffer: usize,
+    palette: usize,
+    enabled: bool,
+    index_high: u8,
+    sprite_width: usize,
+    sprite_height: usize,
+    alpha: u8,
+    blend: Blend,
+    collision: u8,
+    sample: Option<Sample>,
+}
+
+impl MegaChip {
+    /// Creates MEGA-CHIP support with the framebuffer and palette
+    /// at the given memory addresses
+    pub const fn new(framebuffer: usize, palette: usize) -> Self {
+        Self {
+            framebuffer,
+            palette,
+            enabled: false,
+            index_high: 0,
+            sprite_width: 0,
+            sprite_height: 0,
+            alpha: 0xff,
+            blend: Blend::Normal,
+            collision: 0,
+            sample: None,
+        }
+    }
+
+    /// Returns true in MEGA-CHIP mode
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the bytes of memory needed to hold the
+    /// framebuffer and the palette
+    pub fn required_memory(&self) -> usize {
+        (self.framebuffer + FRAMEBUFFER_SIZE).max(self.palette + PALETTE_SIZE)
+    }
+
+    /// Returns the blend mode set by the program
+    pub fn blend(&self) -> Blend {
+        self.blend
+    }
+
+    /// Returns the screen alpha set by the program
+    pub fn alpha(&self) -> u8 {
+        self.alpha
+    }
+
+    /// Returns the sample rate of the sound being played, if any
+    pub fn sample_rate(&self) -> Option<u16> {
+        self.sample.map(|sample| sample.rate)
+    }
+
+    /// Returns the high byte of the 24 bit index register
+    pub(crate) fn index_high(&self) -> u8 {
+        self.index_high
+    }
+
+    pub(crate) fn set_index_high(&mut self, high: u8) {
+        self.index_high = high;
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.index_high = 0;
+        self.sample = None;
+    }
+
+    /// Executes everything but `0010`, `0011`, `01nn nnnn`
+    /// which change the interpreter state
+    pub(crate) fn execute(
+        &mut self,
+        instruction: MegaInstruction,
+        index: usize,
+        bus: &mut dyn Bus,
+    ) {
+        use MegaInstruction::*;
+        match instruction {
+            ScrollUp(n) => {
+                let offset = n as usize * MEGA_WIDTH;
+                for address in 0..FRAMEBUFFER_SIZE {
+                    let value = match address + offset {
+                        source if source < FRAMEBUFFER_SIZE => bus.read(self.framebuffer + source),
+                        _ => 0,
+                    };
+                    bus.write(self.framebuffer + address, value);
+                }
+            }
+            LdPalette(count) => {
+                let mut color = [0; 4];
+                let count = (count as usize).min(bus.len().saturating_sub(index) / 4);
+                for i in 0..count {
+                    bus.read_slice(index + i * 4, &mut color);
+                    bus.write_slice(self.palette + (i + 1) * 4, &color);
+                }
+            }
+            SpriteWidth(nn) => self.sprite_width = nn as usize,
+            SpriteHeight(nn) => self.sprite_height = nn as usize,
+            Alpha(nn) => self.alpha = nn,
+            PlaySound { looping } => {
+                let start = index + SAMPLE_HEADER;
+                if start > bus.len() {
+                    self.sample = None;
+                    return;
+                }
+                let mut header = [0; SAMPLE_HEADER];
+                bus.read_slice(index, &mut header);
+                let length = u32::from_be_bytes([0, header[2], header[3], header[4]]) as usize;
+                self.sample = Some(Sample {
+                    start,
+                    length: length.min(bus.len() - start),
+                    rate: u16::from_be_bytes([header[0], header[1]]),
+                    position: 0,
+                    looping,
+                });
+            }
+            StopSound => self.sample = None,
+            BlendMode(n) => {
+                self.blend = [
+                    Blend::Normal,
+                    Blend::Percent25,
+                    Blend::Percent50,
+                    Blend::Add,
+                    Blend::Multiply,
+                ][n as usize]
+            }
+            Collision(nn) => self.collision = nn,
+            Off | On | LdIFar(_) => {}
+        }
+    }
+
+    /// Draws the sprite at `index` into the framebuffer,
+    /// returning true on a collision
+    pub(crate) fn draw(&self, x: usize, y: usize, index: usize, bus: &mut dyn Bus) -> bool {
+        let width = match self.sprite_width {
+            0 => 256,
+            width => width,
+        };
+        let height = match self.sprite_height {
+            0 => 256,
+            height => height,
+        };
+        let mut collision = false;
+        for row in 0..height.min(MEGA_HEIGHT.saturating_sub(y)) {
+            for column in 0..width.min(MEGA_WIDTH.saturating_sub(x)) {
+                let source = index + row * width + column;
+                if source >= bus.len() {
+                    return collision;
+                }
+                let color = bus.read(source);
+                if color != 0 {
+                    let address = self.framebuffer + (y + row) * MEGA_WIDTH + x + column;
+                    let below = bus.read(address);
+                    collision |= below != 0 && below == self.collision;
+                    let color = self.blend_colors(color, below, bus);
+                    bus.write(address, color);
+                }
+            }
+        }
+        collision
+    }
+
+    /// Returns the palette entry for sprite entry `color`
+    /// drawn over screen entry `below` with the blend mode
+    fn blend_colors(&self, color: u8, below: u8, bus: &mut dyn Bus) -> u8 {
+        if self.blend == Blend::Normal || below == 0 {
+            return color;
+        }
+        let (sprite, screen) = (self.rgb(color, bus), self.rgb(below, bus));
+        let mix = |sprite: u8, screen: u8| {
+            let (sprite, screen) = (sprite as u16, screen as u16);
+            match self.blend {
+                Blend::Normal => sprite,
+                Blend::Percent25 => (sprite + 3 * screen) / 4,
+                Blend::Percent50 => (sprite + screen) / 2,
+                Blend::Add => (sprite + screen).min(0xff),
+                Blend::Multiply => sprite * screen / 0xff,
+            }
+        };
+        let blended = [
+            mix(sprite[0], screen[0]),
+            mix(sprite[1], screen[1]),
+            mix(sprite[2], screen[2]),
+        ];
+        (1..=u8::MAX)
+            .min_by_key(|&entry| {
+                let rgb = self.rgb(entry, bus);
+                (0..3)
+                    .map(|i| (rgb[i] as i32 - blended[i] as i32).pow(2))
+                    .sum::<i32>()
+            })
+            .unwrap_or(color)
+    }
+
+    /// Returns the red, green and blue of a palette entry
+    fn rgb(&self, entry: u8, bus: &mut dyn Bus) -> [u8; 3] {
+        let mut argb = [0; 4];
+        bus.read_slice(self.palette + entry as usize * 4, &mut argb);
+        [argb[1], argb[2], argb[3]]
+    }
+
+    /// Returns the screen color of the framebuffer pixel at `offset`
+    pub(crate) fn color(&self, offset: usize, bus: &mut dyn Bus) -> Rgb565 {
+        let index = bus.read(self.framebuffer + offset) as usize;
+        if index == 0 {
+            return Rgb565::new(0, 0, 0);
+        }
+        let [red, green, blue] = self.rgb(index as u8, bus);
+        let fade = |channel: u8| (channel as u16 * self.alpha as u16 / 0xff) as u8;
+        Rgb565::new(fade(red) >> 3, fade(green) >> 2, fade(blue) >> 3)
+    }
+
+    /// Clears the framebuffer
+    pub(crate) fn clear(&self, bus: &mut dyn Bus) {
+        for offset in 0..FRAMEBUFFER_SIZE {
+            bus.write(self.framebuffer + offset, 0);
+        }
+    }
+
+    /// Returns the next sample of the digitized sound
+    pub(crate) fn next_sample(&mut self, output_rate: u32, bus: &mut dyn Bus) -> Option<u8> {
+        let sample = self.sample.as_mut()?;
+        let mut position = (sample.position >> 16) as usize;
+        if position >= sample.length {
+            if !sample.looping || sample.length == 0 {
+                self.sample = None;
+                return None;
+            }
+            position %= sample.length;
+            sample.position = (position as u64) << 16;
+        }
+        let value = bus.read(sample.start + position);
+        sample.position += ((sample.rate as u64) << 16) / output_rate.max(1) as u64;
+        Some(value)
+    }
+}
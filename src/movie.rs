@@ -0,0 +1,364 @@
+use core::convert::Infallible;
+
+use embedded_hal::blocking::delay::DelayMs;
+
+use crate::keypad::KeySource;
+use crate::platform::Platform;
+
+/// First bytes of every movie
+const MAGIC: [u8; 4] = *b"C8MV";
+/// Format version written by [`Recorder`]
+const VERSION: u8 = 1;
+/// Platform byte of a movie recorded without a platform
+const NO_PLATFORM: u8 = 0xff;
+/// Bytes before the first event
+pub const HEADER_SIZE: usize = 14;
+
+/// Platforms in the order their byte is written
+const PLATFORMS: [Platform; 6] = [
+    Platform::CosmacVip,
+    Platform::Chip48,
+    Platform::SuperChipLegacy,
+    Platform::SuperChipModern,
+    Platform::XoChip,
+    Platform::HiresChip8,
+];
+
+/// Errors reading or writing a movie
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    /// The data does not start with the movie magic bytes
+    BadMagic,
+    /// The movie was written by another version of the format
+    UnsupportedVersion(u8),
+    /// The platform byte is not a known [`Platform`]
+    UnknownPlatform(u8),
+    /// The data ends part way through the header or an event
+    Truncated,
+    /// The buffer given to [`Recorder`] cannot hold the header
+    BufferFull,
+    /// The events run past the last frame a `u32` can count
+    TooLong,
+}
+
+/// How a recorded run was set up, replay it on an interpreter
+/// created with the same platform and [`Xorshift`](crate::rng::Xorshift) seed
+///
+/// Only the platform and the seed are recorded. The program, settings
+/// changed after choosing the platform such as quirks or the memory map,
+/// and the [`PowerOnMemory`](crate::PowerOnMemory) pattern are not, the
+/// player must set those up as they were when recording.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovieHeader {
+    /// The platform the interpreter was set up as, if any
+    pub platform: Option<Platform>,
+    /// The seed of the random number generator
+    pub seed: u64,
+}
+
+impl MovieHeader {
+    fn write(&self, buffer: &mut [u8]) {
+        buffer[..4].copy_from_slice(&MAGIC);
+        buffer[4] = VERSION;
+        buffer[5] = match self.platform {
+            Some(platform) => PLATFORMS.iter().position(|&p| p == platform).unwrap_or(0) as u8,
+            None => NO_PLATFORM,
+        };
+        buffer[6..HEADER_SIZE].copy_from_slice(&self.seed.to_le_bytes());
+    }
+
+    fn read(data: &[u8]) -> Result<Self, MovieError> {
+        if data.len() < MAGIC.len() || data[..4] != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        if data.len() < HEADER_SIZE {
+            return Err(MovieError::Truncated);
+        }
+        if data[4] != VERSION {
+            return Err(MovieError::UnsupportedVersion(data[4]));
+        }
+        let platform = match data[5] {
+            NO_PLATFORM => None,
+            byte => match PLATFORMS.get(byte as usize) {
+                Some(&platform) => Some(platform),
+                None => return Err(MovieError::UnknownPlatform(byte)),
+            },
+        };
+        let mut seed = [0; 8];
+        seed.copy_from_slice(&data[6..HEADER_SIZE]);
+        Ok(Self {
+            platform,
+            seed: u64::from_le_bytes(seed),
+        })
+    }
+}
+
+/// A change of the held keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Event {
+    /// Frames since the previous event
+    frames: u32,
+    /// Scans since the start of the frame
+    scan: u32,
+    keys: u16,
+}
+
+impl Event {
+    /// Writes the event to the start of `buffer`, returning
+    /// its length or `None` if it does not fit
+    fn write(&self, buffer: &mut [u8]) -> Option<usize> {
+        let mut encoded = [0; 12];
+        let mut length = write_varint(self.frames, &mut encoded);
+        length += write_varint(self.scan, &mut encoded[length..]);
+        encoded[length..length + 2].copy_from_slice(&self.keys.to_le_bytes());
+        length += 2;
+        buffer
+            .get_mut(..length)?
+            .copy_from_slice(&encoded[..length]);
+        Some(length)
+    }
+
+    /// Reads an event from the start of `data`, returning it
+    /// and its length
+    fn read(data: &[u8]) -> Result<(Self, usize), MovieError> {
+        let (frames, mut length) = read_varint(data)?;
+        let (scan, scan_length) = read_varint(&data[length..])?;
+        length += scan_length;
+        let keys = data.get(length..length + 2).ok_or(MovieError::Truncated)?;
+        let keys = u16::from_le_bytes([keys[0], keys[1]]);
+        Ok((Self { frames, scan, keys }, length + 2))
+    }
+}
+
+/// Writes a LEB128 number, returning its length
+fn write_varint(mut value: u32, buffer: &mut [u8]) -> usize {
+    let mut length = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buffer[length] = byte;
+            return length + 1;
+        }
+        buffer[length] = byte | 0x80;
+        length += 1;
+    }
+}
+
+/// Reads a LEB128 number, returning it and its length
+fn read_varint(data: &[u8]) -> Result<(u32, usize), MovieError> {
+    let mut value = 0;
+    for (length, &byte) in data.iter().enumerate().take(5) {
+        value |= ((byte & 0x7f) as u32) << (7 * length);
+        if byte & 0x80 == 0 {
+            return Ok((value, length + 1));
+        }
+    }
+    Err(MovieError::Truncated)
+}
+
+/// Records the keys read from another [`KeySource`] into a movie
+///
+/// The movie is a [`MovieHeader`] followed by an event for every change
+/// of the held keys, giving the frame and the number of scans into the
+/// frame so even a program waiting on `fx0a` is replayed exactly. Call
+/// `next_frame` through `Chip8::keypad_mut` at the start of every frame,
+/// the [`Player`] must be given the same calls.
+///
+/// Recording stops without an error when the buffer is full, or when
+/// the frame or scan count no longer fits a `u32`, and the movie only
+/// replays up to that point, check `overflowed` once done.
+pub struct Recorder<'a, K> {
+    keys: K,
+    buffer: &'a mut [u8],
+    length: usize,
+    frame: u32,
+    last_frame: u32,
+    scan: u32,
+    held: u16,
+    overflowed: bool,
+}
+
+impl<'a, K> Recorder<'a, K>
+where
+    K: KeySource,
+{
+    /// Creates a recorder writing to `buffer`, which needs
+    /// at least [`HEADER_SIZE`] bytes
+    pub fn new(keys: K, buffer: &'a mut [u8], header: MovieHeader) -> Result<Self, MovieError> {
+        if buffer.len() < HEADER_SIZE {
+            return Err(MovieError::BufferFull);
+        }
+        header.write(buffer);
+        Ok(Self {
+            keys,
+            buffer,
+            length: HEADER_SIZE,
+            frame: 0,
+            last_frame: 0,
+            scan: 0,
+            held: 0,
+            overflowed: false,
+        })
+    }
+
+    /// Starts the next frame
+    pub fn next_frame(&mut self) {
+        match self.frame.checked_add(1) {
+            Some(frame) => self.frame = frame,
+            None => self.overflowed = true,
+        }
+        self.scan = 0;
+    }
+
+    /// Returns the frame being recorded
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Returns the movie recorded so far
+    pub fn data(&self) -> &[u8] {
+        &self.buffer[..self.length]
+    }
+
+    /// Returns true if events were lost because the buffer
+    /// filled up or a count ran out
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    /// Gives back the key source, returning the
+    /// length of the movie in the buffer
+    pub fn release(self) -> (K, usize) {
+        (self.keys, self.length)
+    }
+}
+
+impl<K> KeySource for Recorder<'_, K>
+where
+    K: KeySource,
+{
+    type Error = K::Error;
+
+    fn scan<D>(&mut self, delay: &mut D) -> Result<u16, Self::Error>
+    where
+        D: DelayMs<u32>,
+    {
+        let keys = self.keys.scan(delay)?;
+        if keys != self.held && !self.overflowed {
+            let event = Event {
+                frames: self.frame - self.last_frame,
+                scan: self.scan,
+                keys,
+            };
+            match event.write(&mut self.buffer[self.length..]) {
+                Some(length) => {
+                    self.length += length;
+                    self.last_frame = self.frame;
+                    self.held = keys;
+                }
+                None => self.overflowed = true,
+            }
+        }
+        match self.scan.checked_add(1) {
+            Some(scan) => self.scan = scan,
+            None => self.overflowed = true,
+        }
+        match self.overflowed {
+            true => Ok(keys),
+            false => Ok(self.held),
+        }
+    }
+}
+
+/// Plays back the keys of a movie made by [`Recorder`]
+///
+/// Set the interpreter up from `header` before running it and call
+/// `next_frame` wherever it was called while recording.
+pub struct Player<'a> {
+    header: MovieHeader,
+    events: &'a [u8],
+    next: Option<Event>,
+    frame: u32,
+    event_frame: u32,
+    scan: u32,
+    held: u16,
+}
+
+impl<'a> Player<'a> {
+    /// Creates a player, checking the whole movie first
+    pub fn new(data: &'a [u8]) -> Result<Self, MovieError> {
+        let header = MovieHeader::read(data)?;
+        let mut events = &data[HEADER_SIZE..];
+        let mut frame: u32 = 0;
+        while !events.is_empty() {
+            let (event, length) = Event::read(events)?;
+            frame = frame.checked_add(event.frames).ok_or(MovieError::TooLong)?;
+            events = &events[length..];
+        }
+        let mut player = Self {
+            header,
+            events: &data[HEADER_SIZE..],
+            next: None,
+            frame: 0,
+            event_frame: 0,
+            scan: 0,
+            held: 0,
+        };
+        player.read_next();
+        Ok(player)
+    }
+
+    /// Returns how the recorded run was set up
+    pub fn header(&self) -> MovieHeader {
+        self.header
+    }
+
+    /// Starts the next frame
+    pub fn next_frame(&mut self) {
+        self.frame = self.frame.saturating_add(1);
+        self.scan = 0;
+    }
+
+    /// Returns the frame being played
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+
+    /// Returns true once every event has been played
+    pub fn finished(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Takes the next event from the movie
+    fn read_next(&mut self) {
+        self.next = match Event::read(self.events) {
+            Ok((event, length)) => {
+                self.events = &self.events[length..];
+                self.event_frame += event.frames;
+                Some(event)
+            }
+            Err(_) => None,
+        };
+    }
+}
+
+impl KeySource for Player<'_> {
+    type Error = Infallible;
+
+    fn scan<D>(&mut self, _delay: &mut D) -> Result<u16, Self::Error>
+    where
+        D: DelayMs<u32>,
+    {
+        while let Some(event) = self.next {
+            let due = (self.event_frame, event.scan) <= (self.frame, self.scan);
+            if !due {
+                break;
+            }
+            self.held = event.keys;
+            self.read_next();
+        }
+        self.scan = self.scan.saturating_add(1);
+        Ok(self.held)
+    }
+}
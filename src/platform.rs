@@ -0,0 +1,177 @@
+use crate::fonts::{self, Font};
+use crate::instruction::InstructionSet;
+use crate::{MemoryLayout, MemoryMap, Resolution};
+
+/// How `fx55` and `fx65` leave the index register
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// The index is unchanged
+    None,
+    /// The index is increased by x, as on CHIP-48
+    X,
+    /// The index is increased by x + 1, as on the COSMAC VIP
+    XPlusOne,
+}
+
+/// Behaviour that differs between interpreters
+///
+/// The default turns every quirk off, a [`Platform`]
+/// gives the quirks of a real interpreter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8xy1`, `8xy2` and `8xy3` set vf to 0
+    pub vf_reset: bool,
+    /// `8xy6` and `8xye` shift vy into vx instead of shifting vx
+    pub shift_vy: bool,
+    /// `bxnn` jumps to xnn plus vx instead of nnn plus v0
+    pub jump_vx: bool,
+    /// How `fx55` and `fx65` change the index
+    pub index_increment: IndexIncrement,
+    /// Sprites wrap around the screen edges instead of being clipped
+    pub wrap: bool,
+    /// At high resolution `dxyn` sets vf to the number
+    /// of sprite rows that collided
+    pub row_collisions: bool,
+    /// `00fe` and `00ff` clear the screen
+    pub clear_on_resolution: bool,
+    /// `fx1e` sets vf to 1 when the index passes the end of
+    /// memory, as the Amiga interpreter did
+    pub index_overflow: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            vf_reset: false,
+            shift_vy: false,
+            jump_vx: false,
+            index_increment: IndexIncrement::None,
+            wrap: false,
+            row_collisions: false,
+            clear_on_resolution: false,
+            index_overflow: false,
+        }
+    }
+}
+
+/// A family of chip8 interpreters
+///
+/// Each platform selects the instruction set, quirks, resolution, memory
+/// map and fonts of the interpreter it stands for, use it with
+/// `Chip8::with_platform` or `Chip8::reset_platform`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    /// The original interpreter on the RCA COSMAC VIP
+    CosmacVip,
+    /// CHIP-48 on the HP-48 calculators
+    Chip48,
+    /// SUPER-CHIP 1.1 as it ran on the HP-48
+    SuperChipLegacy,
+    /// SUPER-CHIP as modern interpreters implement it
+    SuperChipModern,
+    /// XO-CHIP, which needs 64K of memory such as `Ram<65536>`
+    XoChip,
+    /// HIRES CHIP-8, 64x64 programs for the COSMAC VIP
+    HiresChip8,
+}
+
+impl Platform {
+    /// Returns the instructions the platform recognises
+    pub fn instruction_set(&self) -> InstructionSet {
+        match self {
+            Platform::CosmacVip | Platform::Chip48 | Platform::HiresChip8 => InstructionSet::Chip8,
+            Platform::SuperChipLegacy | Platform::SuperChipModern => InstructionSet::SuperChip,
+            Platform::XoChip => InstructionSet::XoChip,
+        }
+    }
+
+    /// Returns the quirks of the platform
+    pub fn quirks(&self) -> Quirks {
+        let vip = Quirks {
+            vf_reset: true,
+            shift_vy: true,
+            index_increment: IndexIncrement::XPlusOne,
+            ..Quirks::default()
+        };
+        let super_chip = Quirks {
+            jump_vx: true,
+            ..Quirks::default()
+        };
+        match self {
+            Platform::CosmacVip | Platform::HiresChip8 => vip,
+            Platform::Chip48 => Quirks {
+                index_increment: IndexIncrement::X,
+                ..super_chip
+            },
+            Platform::SuperChipLegacy => Quirks {
+                row_collisions: true,
+                ..super_chip
+            },
+            Platform::SuperChipModern => Quirks {
+                clear_on_resolution: true,
+                ..super_chip
+            },
+            Platform::XoChip => Quirks {
+                shift_vy: true,
+                index_increment: IndexIncrement::XPlusOne,
+                wrap: true,
+                clear_on_resolution: true,
+                ..Quirks::default()
+            },
+        }
+    }
+
+    /// Returns the resolution programs start in
+    pub fn resolution(&self) -> Resolution {
+        match self {
+            Platform::HiresChip8 => Resolution::Hires,
+            _ => Resolution::Standard,
+        }
+    }
+
+    /// Returns the memory map
+    pub fn memory_map(&self) -> MemoryMap {
+        match self {
+            Platform::CosmacVip => MemoryMap::COSMAC_VIP,
+            Platform::HiresChip8 => MemoryMap::HIRES,
+            Platform::XoChip => MemoryMap::XO_CHIP,
+            _ => MemoryMap::CHIP_8,
+        }
+    }
+
+    /// Returns where the display and stack are kept, in memory
+    /// for the VIP platforms so programs can read them
+    pub fn memory_layout(&self) -> MemoryLayout {
+        match self {
+            Platform::CosmacVip | Platform::HiresChip8 => MemoryLayout::Vip,
+            _ => MemoryLayout::Separate,
+        }
+    }
+
+    /// Returns the small font
+    pub fn font(&self) -> Font {
+        match self {
+            Platform::CosmacVip | Platform::HiresChip8 => fonts::VIP,
+            _ => fonts::CHIP_48,
+        }
+    }
+
+    /// Returns the large font, if the platform has one
+    pub fn big_font(&self) -> Option<Font> {
+        match self {
+            Platform::SuperChipLegacy | Platform::SuperChipModern => Some(fonts::SUPER_CHIP_BIG),
+            Platform::XoChip => Some(fonts::XO_CHIP_BIG),
+            _ => None,
+        }
+    }
+
+    /// Returns how many instructions to run per 60Hz frame
+    /// for programs to play at their intended speed
+    pub fn instructions_per_frame(&self) -> u32 {
+        match self {
+            Platform::CosmacVip | Platform::HiresChip8 => 15,
+            Platform::Chip48 | Platform::SuperChipLegacy | Platform::SuperChipModern => 30,
+            Platform::XoChip => 1000,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use rand::RngCore;
+
+/// Seed used when given 0, which xorshift cannot leave
+const ZERO_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// A small seedable random number generator, xorshift64*
+///
+/// For hardware without a random number generator, and for runs that
+/// must be repeated exactly such as tests and replays. The whole state
+/// is one `u64` from `state` that can be saved and restored with
+/// `set_state`, and is included in a `Chip8::snapshot`.
+/// Use it with `Chip8::with_seed`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Default for Xorshift {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Xorshift {
+    /// Creates a generator from a seed
+    pub const fn new(seed: u64) -> Self {
+        Self {
+            state: match seed {
+                0 => ZERO_SEED,
+                seed => seed,
+            },
+        }
+    }
+
+    /// Restarts the sequence from a seed
+    pub fn seed(&mut self, seed: u64) {
+        *self = Self::new(seed);
+    }
+
+    /// Returns the current state
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    /// Restores a state from `state`
+    pub fn set_state(&mut self, state: u64) {
+        self.seed(state);
+    }
+}
+
+impl RngCore for Xorshift {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
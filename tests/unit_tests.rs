@@ -1,11 +1,23 @@
 #[cfg(test)]
 mod chip8 {
-    use chip8::fonts::DEFAULT;
-    use chip8::keypad::KeyPad;
-    use chip8::Chip8;
+    use chip8::bus::{Bus, MappedBus, Ram};
+    use chip8::cdp1802::Cdp1802;
+    use chip8::chip8x::Chip8X;
+    use chip8::extension::{Machine, OpcodeExtension, PcAdvance};
+    use chip8::fonts::{DEFAULT, VIP, XO_CHIP_BIG};
+    use chip8::framebuffer::{Framebuffer, CHIP8_SIZE};
+    use chip8::keypad::{KeyPad, KeySource};
+    use chip8::megachip::MegaChip;
+    use chip8::platform::{Platform, Quirks};
+    use chip8::{
+        Chip8, Error, InstructionSet, MemoryLayout, MemoryMap, PowerOnMemory, Resolution,
+        UnknownOpcodePolicy,
+    };
+    use embedded_graphics::image::Image;
     use embedded_graphics::mock_display::MockDisplay;
     use embedded_graphics::pixelcolor::Rgb565;
-    use embedded_hal::digital::v2::{InputPin, OutputPin};
+    use embedded_graphics::prelude::*;
+    use embedded_hal::blocking::delay::DelayMs;
     use embedded_hal_mock::{
         common::Generic,
         delay::MockNoop as MockDelay,
@@ -30,16 +42,15 @@ mod chip8 {
 
     type MockChip8 = Chip8<
         MockDisplay<Rgb565>,
-        Generic<PinTransaction>,
-        Generic<PinTransaction>,
+        KeyPad<Generic<PinTransaction>, Generic<PinTransaction>>,
         MockRng,
         MockDelay,
     >;
 
-    fn get_fixture_no_keypad() -> MockChip8 {
+    fn get_keypad_no_keys() -> KeyPad<Generic<PinTransaction>, Generic<PinTransaction>> {
         let expect = [PinTransaction::set(PinState::High)];
 
-        let keypad = KeyPad::new(
+        KeyPad::new(
             [
                 MockPin::new(&expect),
                 MockPin::new(&expect),
@@ -52,14 +63,15 @@ mod chip8 {
                 MockPin::new(&[]),
                 MockPin::new(&[]),
             ],
-        );
+        )
+        .unwrap()
+    }
 
-        let mut chip8 = Chip8::new(
-            MockDisplay::<Rgb565>::new(),
-            keypad,
-            MockRng {},
-            MockDelay::default(),
-        );
+    fn get_fixture_no_keypad() -> MockChip8 {
+        let mut display = MockDisplay::<Rgb565>::new();
+        display.set_allow_overdraw(true);
+        display.set_allow_out_of_bounds_drawing(true);
+        let mut chip8 = Chip8::new(display, get_keypad_no_keys(), MockRng {}, MockDelay::new());
 
         chip8.load_font(DEFAULT);
 
@@ -70,21 +82,52 @@ mod chip8 {
     fn _1nnn() {
         let mut chip8 = get_fixture_no_keypad();
         chip8.load_program(&[0x12, 0x02, 0x1f, 0xff]);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0x202);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0xfff);
     }
 
     #[test]
     fn _2nnn() {}
 
+    #[test]
+    fn _2nnn_overflow() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0x22, 0x00]);
+        for _ in 0..15 {
+            chip8.tick().unwrap();
+        }
+        let error = Error::StackOverflow { address: 0x200 };
+        assert_eq!(chip8.tick(), Err(error));
+        assert_eq!(chip8.get_stack_pointer(), 15);
+    }
+
+    #[test]
+    fn _00ee_underflow() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0x00, 0xee]);
+        let error = Error::StackUnderflow { address: 0x200 };
+        assert_eq!(chip8.tick(), Err(error));
+        assert_eq!(chip8.get_program_counter(), 0x200);
+        assert_eq!(chip8.get_stack_pointer(), 0);
+
+        chip8.reset();
+        chip8.set_memory_layout(MemoryLayout::Vip);
+        chip8.load_program(&[0x22, 0x04, 0x00, 0x00, 0x00, 0xee]);
+        chip8.tick().unwrap();
+        chip8.write_memory(0xece, 0x00);
+        chip8.write_memory(0xecf, 0x01);
+        let error = Error::StackUnderflow { address: 0x204 };
+        assert_eq!(chip8.tick(), Err(error));
+    }
+
     #[test]
     fn _3xnn_true() {
         let mut chip8 = get_fixture_no_keypad();
         chip8.load_program(&[0x30, 0xff]);
         chip8.write_register(0x0, 0xff);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0x204);
     }
 
@@ -93,7 +136,7 @@ mod chip8 {
         let mut chip8 = get_fixture_no_keypad();
         chip8.load_program(&[0x30, 0x00]);
         chip8.write_register(0x0, 0xff);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0x202);
     }
 
@@ -102,7 +145,7 @@ mod chip8 {
         let mut chip8 = get_fixture_no_keypad();
         chip8.load_program(&[0x40, 0x00]);
         chip8.write_register(0x0, 0xff);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0x204);
     }
 
@@ -111,7 +154,7 @@ mod chip8 {
         let mut chip8 = get_fixture_no_keypad();
         chip8.load_program(&[0x40, 0xff]);
         chip8.write_register(0x0, 0xff);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0x202);
     }
 
@@ -121,7 +164,7 @@ mod chip8 {
         chip8.load_program(&[0x50, 0x10]);
         chip8.write_register(0x0, 0xff);
         chip8.write_register(0x1, 0xff);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0x204);
     }
 
@@ -131,7 +174,7 @@ mod chip8 {
         chip8.load_program(&[0x50, 0x10]);
         chip8.write_register(0x0, 0x00);
         chip8.write_register(0x1, 0xff);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0x202);
     }
 
@@ -139,7 +182,7 @@ mod chip8 {
     fn _6xnn() {
         let mut chip8 = get_fixture_no_keypad();
         chip8.load_program(&[0x60, 0xa]);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_registers()[0x0], 0xa);
         assert_eq!(chip8.get_program_counter(), 0x202);
     }
@@ -149,7 +192,7 @@ mod chip8 {
         let mut chip8 = get_fixture_no_keypad();
         chip8.load_program(&[0x70, 0x09]);
         chip8.write_register(0x0, 0x01);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_registers()[0x0], 0xa);
         assert_eq!(chip8.get_program_counter(), 0x202);
     }
@@ -159,18 +202,95 @@ mod chip8 {
         let mut chip8 = get_fixture_no_keypad();
         chip8.load_program(&[0x70, 0x01]);
         chip8.write_register(0x0, 0xff);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_registers()[0x0], 0x00);
         assert_eq!(chip8.get_program_counter(), 0x202);
     }
 
+    #[test]
+    fn _8xy5() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0x80, 0x15, 0x80, 0x15]);
+        chip8.write_register(0x0, 0x05);
+        chip8.write_register(0x1, 0x03);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0x0], 0x02);
+        assert_eq!(chip8.get_registers()[0xf], 1);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0x0], 0xff);
+        assert_eq!(chip8.get_registers()[0xf], 0);
+    }
+
+    #[test]
+    fn _8xy7() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0x80, 0x17, 0x80, 0x17]);
+        chip8.write_register(0x0, 0x03);
+        chip8.write_register(0x1, 0x05);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0x0], 0x02);
+        assert_eq!(chip8.get_registers()[0x1], 0x05);
+        assert_eq!(chip8.get_registers()[0xf], 1);
+        chip8.write_register(0x0, 0x06);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0x0], 0xff);
+        assert_eq!(chip8.get_registers()[0xf], 0);
+    }
+
+    #[test]
+    fn _8xye() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0x80, 0x0e, 0x80, 0x0e]);
+        chip8.write_register(0x0, 0x81);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0x0], 0x02);
+        assert_eq!(chip8.get_registers()[0xf], 1);
+        chip8.write_register(0x0, 0x08);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0x0], 0x10);
+        assert_eq!(chip8.get_registers()[0xf], 0);
+    }
+
+    #[test]
+    fn _fx1e() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0xf2, 0x1e]);
+        chip8.set_index(0x300);
+        chip8.write_register(0x2, 0x10);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_index(), 0x310);
+        assert_eq!(chip8.get_registers()[0xf], 0);
+    }
+
+    #[test]
+    fn _fx29() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0xf0, 0x29]);
+        chip8.write_register(0x0, 0xa);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_index(), 0x50 + 0xa * 5);
+    }
+
+    #[test]
+    fn _fx30() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.set_font_addresses(0x000, 0x100);
+        chip8.load_font(VIP);
+        chip8.load_big_font(XO_CHIP_BIG);
+        chip8.load_program(&[0xf0, 0x30]);
+        chip8.write_register(0x0, 0x2);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_index(), 0x100 + 0x2 * 10);
+        assert_eq!(chip8.get_program_counter(), 0x202);
+    }
+
     #[test]
     fn _fx33() {
         let mut chip8 = get_fixture_no_keypad();
         chip8.load_program(&[0xf0, 0x33]);
         chip8.write_register(0x0, 0xff);
         chip8.set_index(0x202);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0x202);
         assert_eq!(chip8.get_program_memory()[0x2], 0x2);
         assert_eq!(chip8.get_program_memory()[0x3], 0x5);
@@ -199,7 +319,7 @@ mod chip8 {
         chip8.write_register(0x0e, 0xff);
         chip8.write_register(0x0f, 0xff);
         println!("{:#?}", chip8.get_current_op());
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0x202);
         assert_eq!(chip8.get_program_memory()[0x03], 0xff);
         assert_eq!(chip8.get_program_memory()[0x12], 0xff);
@@ -215,9 +335,1681 @@ mod chip8 {
             0xff, 0xff, 0xff, 0xff,
         ]);
         chip8.set_index(0x203);
-        chip8.tick();
+        chip8.tick().unwrap();
         assert_eq!(chip8.get_program_counter(), 0x202);
         assert_eq!(chip8.get_registers()[0x0], 0xff);
         assert_eq!(chip8.get_registers()[0xf], 0xff);
     }
+
+    #[test]
+    fn unknown_opcode_ignore() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0x51, 0x21]);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_program_counter(), 0x202);
+    }
+
+    #[test]
+    fn unknown_opcode_halt() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.set_unknown_opcode_policy(UnknownOpcodePolicy::Halt);
+        chip8.load_program(&[0x60, 0x01, 0xe0, 0x9f]);
+        chip8.tick().unwrap();
+        let error = Error::UnknownOpcode {
+            address: 0x202,
+            opcode: (0xe0, 0x9f),
+        };
+        assert_eq!(chip8.tick(), Err(error));
+        assert_eq!(chip8.tick(), Err(error));
+        assert_eq!(chip8.get_error(), Some(error));
+        assert_eq!(chip8.get_program_counter(), 0x202);
+        chip8.reset();
+        assert_eq!(chip8.get_error(), None);
+    }
+
+    #[test]
+    fn unknown_opcode_halt_zeroed_memory() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.set_unknown_opcode_policy(UnknownOpcodePolicy::Halt);
+        chip8.load_program(&[0x12, 0x10]);
+        chip8.tick().unwrap();
+        let error = Error::UnknownOpcode {
+            address: 0x210,
+            opcode: (0x00, 0x00),
+        };
+        assert_eq!(chip8.tick(), Err(error));
+        assert_eq!(chip8.get_program_counter(), 0x210);
+    }
+
+    /// A peripheral that faults once written to
+    struct Broken(bool);
+
+    impl Bus for Broken {
+        fn len(&self) -> usize {
+            1
+        }
+        fn read(&mut self, _address: usize) -> u8 {
+            0
+        }
+        fn write(&mut self, _address: usize, _value: u8) {
+            self.0 = true;
+        }
+        fn faulted(&self) -> bool {
+            self.0
+        }
+    }
+
+    #[test]
+    fn bus_fault() {
+        let chip8 = get_fixture_no_keypad();
+        let mut chip8 = chip8.with_bus(MappedBus::new(Ram::<4096>::new(), 0x300, Broken(false)));
+        chip8.load_program(&[0xa3, 0x00, 0xf0, 0x55, 0x00, 0xe0]);
+        chip8.tick().unwrap();
+        let error = Error::BusFault { address: 0x202 };
+        assert_eq!(chip8.tick(), Err(error));
+        assert_eq!(chip8.tick(), Err(error));
+        assert!(chip8.bus_mut().peripheral_mut().0);
+    }
+
+    #[test]
+    fn current_op_peeks_the_bus() {
+        let chip8 = get_fixture_no_keypad();
+        let mut chip8 = chip8.with_bus(MappedBus::new(Ram::<4096>::new(), 0x200, Ram::<16>::new()));
+        chip8.load_program(&[0x60, 0x2a]);
+        let chip8 = &chip8;
+        assert_eq!(chip8.get_current_op(), (0x6, 0x0, 0x2, 0xa));
+        assert_eq!(
+            chip8.get_current_instruction(),
+            Some(chip8::Instruction::LdByte { x: 0, nn: 0x2a })
+        );
+    }
+
+    #[test]
+    fn unknown_opcode_callback() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        static SEEN: AtomicU32 = AtomicU32::new(0);
+        fn callback(address: u16, opcode: (u8, u8)) {
+            SEEN.store(
+                ((address as u32) << 16) | ((opcode.0 as u32) << 8) | opcode.1 as u32,
+                Ordering::SeqCst,
+            );
+        }
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.set_unknown_opcode_policy(UnknownOpcodePolicy::Callback(callback));
+        chip8.load_program(&[0xf3, 0xff]);
+        chip8.tick().unwrap();
+        assert_eq!(SEEN.load(Ordering::SeqCst), 0x0200_f3ff);
+        assert_eq!(chip8.get_program_counter(), 0x202);
+    }
+
+    /// Handles 0x0100 by setting v0, 0xf?ff by storing vx at index
+    /// and skipping, and counts the opcodes it was offered
+    struct LabExtension {
+        offered: usize,
+    }
+
+    impl OpcodeExtension for LabExtension {
+        fn execute(&mut self, opcode: (u8, u8), machine: &mut Machine) -> Option<PcAdvance> {
+            self.offered += 1;
+            match opcode {
+                (0x01, 0x00) => {
+                    machine.registers[0] = 0x42;
+                    Some(PcAdvance::Next)
+                }
+                (x, 0xff) if x & 0xf0 == 0xf0 => {
+                    let value = machine.registers[(x & 0xf) as usize];
+                    machine.memory.write(*machine.index as usize, value);
+                    Some(PcAdvance::Skip)
+                }
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn extension() {
+        let mut chip8 = get_fixture_no_keypad().with_extension(LabExtension { offered: 0 });
+        chip8.set_unknown_opcode_policy(UnknownOpcodePolicy::Halt);
+        chip8.load_program(&[0x01, 0x00, 0xf0, 0xff, 0x00, 0x00, 0x51, 0x21]);
+        chip8.set_index(0x300);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0], 0x42);
+        assert_eq!(chip8.get_program_counter(), 0x202);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_program_counter(), 0x206);
+        assert_eq!(chip8.get_program_memory()[0x100], 0x42);
+        assert!(chip8.tick().is_err());
+        assert_eq!(chip8.extension_mut().offered, 3);
+    }
+
+    #[test]
+    fn vip_layout() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.set_memory_layout(MemoryLayout::Vip);
+        // Draw the 0 glyph at (4, 1), call a subroutine that reads back
+        // the display and return
+        chip8.load_program(&[
+            0x60, 0x04, 0x61, 0x01, 0x62, 0x00, 0xf2, 0x29, 0xd0, 0x15, 0x22, 0x0e, 0x12, 0x0c,
+            0xaf, 0x08, 0xf1, 0x65, 0x00, 0xee,
+        ]);
+        for _ in 0..6 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.read_memory(0xece), 0x02);
+        assert_eq!(chip8.read_memory(0xecf), 0x0c);
+        assert_eq!(chip8.get_stack(), [0; 16]);
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_program_counter(), 0x20c);
+        assert_eq!(chip8.get_registers()[..2], [0x0f, 0x00]);
+        assert_eq!(chip8.read_memory(0xf10), 0x09);
+        chip8.load_program(&[0x00, 0xe0]);
+        chip8.set_program_counter(0x200);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.read_memory(0xf08), 0x00);
+    }
+
+    #[test]
+    fn vip_stack_hires() {
+        let mut display = MockDisplay::<Rgb565>::new();
+        display.set_allow_overdraw(true);
+        display.set_allow_out_of_bounds_drawing(true);
+        let mut chip8 = Chip8::with_memory_map(
+            display,
+            get_keypad_no_keys(),
+            MockRng {},
+            MockDelay::new(),
+            MemoryMap::HIRES,
+        );
+        chip8.set_memory_layout(MemoryLayout::Vip);
+        chip8.load_program(&[0x22, 0x04, 0x00, 0x00, 0x00, 0xee]);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.read_memory(0xdce), 0x02);
+        assert_eq!(chip8.read_memory(0xdcf), 0x02);
+        // The stack stays put when the display grows
+        chip8.set_resolution(Resolution::Hires);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_program_counter(), 0x202);
+    }
+
+    #[test]
+    fn cdp1802() {
+        let chip8 = get_fixture_no_keypad();
+        let mut chip8 = chip8.with_extension(Cdp1802::new());
+        chip8.load_program(&[0x63, 0x05, 0xa3, 0x40, 0x03, 0x00, 0x73, 0x01]);
+        // Store 0x2a at i, add 2 to v3 through its copy at 0xef3 and return
+        for (i, byte) in [
+            0xf8, 0x2a, 0x5a, 0xf8, 0x0e, 0xbf, 0xf8, 0xf3, 0xaf, 0xef, 0xf8, 0x02, 0xf4, 0x5f,
+            0xd4,
+        ]
+        .iter()
+        .enumerate()
+        {
+            chip8.write_memory(0x300 + i as u16, *byte);
+        }
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.read_memory(0x340), 0x2a);
+        assert_eq!(chip8.get_registers()[3], 0x08);
+        assert_eq!(chip8.get_program_counter(), 0x208);
+        assert_eq!(chip8.extension_mut().registers()[5], 0x206);
+    }
+
+    #[test]
+    fn cdp1802_runaway() {
+        let chip8 = get_fixture_no_keypad();
+        let mut chip8 = chip8.with_extension(Cdp1802::new());
+        // A routine that branches to itself forever
+        chip8.load_program(&[0x03, 0x00]);
+        chip8.write_memory(0x300, 0x30);
+        chip8.write_memory(0x301, 0x00);
+        chip8.extension_mut().set_max_cycles(100);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_program_counter(), 0x202);
+        assert_eq!(chip8.extension_mut().registers()[3], 0x300);
+    }
+
+    #[test]
+    fn memory_map() {
+        let mut display = MockDisplay::<Rgb565>::new();
+        display.set_allow_overdraw(true);
+        let mut chip8 = Chip8::with_memory_map(
+            display,
+            get_keypad_no_keys(),
+            MockRng,
+            MockDelay::new(),
+            MemoryMap::ETI_660,
+        );
+        assert_eq!(chip8.get_program_counter(), 0x600);
+        chip8.load_program(&[0x60, 0x2a]);
+        assert_eq!(chip8.get_program_memory()[..2], [0x60, 0x2a]);
+        assert_eq!(chip8.get_program_memory().len(), 0xa00);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0], 0x2a);
+        chip8.reset();
+        assert_eq!(chip8.get_program_counter(), 0x600);
+
+        let mut chip8 = chip8.with_bus(Ram::<0x10000>::new());
+        assert_eq!(chip8.get_memory_map(), MemoryMap::ETI_660);
+        chip8.load_program(&[0xff; 0x1000]);
+        assert_eq!(chip8.read_memory(0xfff), 0xff);
+        assert_eq!(chip8.read_memory(0x1000), 0x00);
+    }
+
+    #[test]
+    fn memory_map_larger_than_bus() {
+        let mut chip8 = Chip8::with_memory_map(
+            MockDisplay::<Rgb565>::new(),
+            get_keypad_no_keys(),
+            MockRng,
+            MockDelay::new(),
+            MemoryMap::XO_CHIP,
+        );
+        chip8.load_program(&[0xff; 0x2000]);
+        assert_eq!(chip8.get_program_memory().len(), 0xe00);
+        // fx1e flags an index past the end of the 4K bus
+        // with the index_overflow quirk
+        chip8.load_program(&[0xaf, 0xff, 0x61, 0x01, 0xf1, 0x1e, 0xf1, 0x1e]);
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_registers()[0xf], 0);
+        chip8.set_quirks(Quirks {
+            index_overflow: true,
+            ..Quirks::default()
+        });
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0xf], 1);
+    }
+
+    #[test]
+    fn memory_wraps_past_the_end() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[
+            0x60, 0x07, 0x61, 0x08, 0xaf, 0xff, 0xf1, 0x55, 0x60, 0x10, 0xbf, 0xff,
+        ]);
+        for _ in 0..6 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.read_memory(0xfff), 0x07);
+        assert_eq!(chip8.read_memory(0x000), 0x08);
+        assert_eq!(chip8.get_program_counter(), 0x100f);
+        // The fetch wraps to the start of memory instead of panicking
+        let _ = chip8.tick();
+    }
+
+    #[test]
+    fn chip10() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.set_memory_layout(MemoryLayout::Vip);
+        chip8.set_resolution(Resolution::Chip10);
+        chip8.set_scale((2, 2));
+        chip8.load_program(&[0x60, 0x64, 0x61, 0x28, 0xa0, 0x50, 0xd0, 0x15, 0xd0, 0x15]);
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        // Display rows are 16 bytes starting at 0xC00
+        assert_eq!(chip8.read_memory(0xe8c), 0x0f);
+        assert_eq!(chip8.read_memory(0xe9c), 0x09);
+        assert_eq!(chip8.get_registers()[0xf], 0);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.read_memory(0xe8c), 0x00);
+        assert_eq!(chip8.get_registers()[0xf], 1);
+    }
+
+    #[test]
+    fn hires_detection() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.set_memory_layout(MemoryLayout::Vip);
+        chip8.set_hires_detection(true);
+        chip8.load_program(&[0x12, 0x60]);
+        chip8.write_memory(0x2c0, 0x02);
+        chip8.write_memory(0x2c1, 0x30);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_resolution(), Resolution::Hires);
+        assert_eq!(chip8.get_program_counter(), 0x2c0);
+        chip8.write_memory(0xe00, 0xff);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.read_memory(0xe00), 0x00);
+        assert_eq!(chip8.get_program_counter(), 0x2c2);
+    }
+
+    #[test]
+    fn hires_platform() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.reset_platform(Platform::HiresChip8);
+        // A patched image: the jump over the interpreter patch,
+        // the patch itself and the program at 0x2C0
+        let mut image = [0; 0xc4];
+        image[..2].copy_from_slice(&[0x12, 0x60]);
+        image[0x60..0x62].copy_from_slice(&[0x00, 0xe0]);
+        image[0xc0..].copy_from_slice(&[0x60, 0x2a, 0x12, 0xc2]);
+        chip8.load_program(&image);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_resolution(), Resolution::Hires);
+        assert_eq!(chip8.get_program_counter(), 0x2c0);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0], 0x2a);
+    }
+
+    #[test]
+    fn megachip() {
+        let chip8 = get_fixture_no_keypad();
+        let mut chip8 = chip8.with_bus(Ram::<0x20000>::new());
+        chip8
+            .set_megachip(Some(MegaChip::new(0x12000, 0x1e000)))
+            .unwrap();
+        chip8.load_program(&[
+            0x00, 0x11, 0x01, 0x00, 0x03, 0x00, 0x02, 0x01, 0x01, 0x00, 0x03, 0x04, 0x03, 0x02,
+            0x04, 0x01, 0x60, 0x0a, 0x61, 0x05, 0xd0, 0x11, 0x09, 0x01, 0xd0, 0x11, 0x00, 0xe0,
+            0x01, 0x01, 0x00, 0x00, 0x06, 0x01,
+        ]);
+        // A red palette entry, a two pixel sprite and a three sample sound
+        chip8
+            .bus_mut()
+            .write_slice(0x300, &[0xff, 0xff, 0x00, 0x00, 0x01, 0x00]);
+        chip8.bus_mut().write_slice(
+            0x10000,
+            &[0x1f, 0x40, 0x00, 0x00, 0x03, 0x00, 0x10, 0x20, 0x30],
+        );
+        for _ in 0..9 {
+            chip8.tick().unwrap();
+        }
+        assert!(chip8.get_megachip().unwrap().enabled());
+        assert_eq!(chip8.get_index(), 0x304);
+        assert_eq!(chip8.bus_mut().read(0x1e004), 0xff);
+        assert_eq!(chip8.bus_mut().read(0x1e005), 0xff);
+        assert_eq!(chip8.get_registers()[0xf], 0);
+        let pixel = 0x12000 + 5 * 256 + 10;
+        assert_eq!(chip8.bus_mut().read(pixel), 0x01);
+        assert_eq!(chip8.bus_mut().read(pixel + 1), 0x00);
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0xf], 1);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.bus_mut().read(pixel), 0x00);
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_megachip().unwrap().sample_rate(), Some(8000));
+        assert_eq!(chip8.next_sample(8000), Some(0x10));
+        assert_eq!(chip8.next_sample(8000), Some(0x20));
+        assert_eq!(chip8.next_sample(8000), Some(0x30));
+        assert_eq!(chip8.next_sample(8000), None);
+    }
+
+    #[test]
+    fn megachip_blend() {
+        let mut chip8 = get_fixture_no_keypad();
+        assert_eq!(
+            chip8.set_megachip(Some(MegaChip::new(0x12000, 0x1e000))),
+            Err(Error::BusTooSmall { required: 0x1e400 })
+        );
+        assert!(chip8.get_megachip().is_none());
+        let mut chip8 = chip8.with_bus(Ram::<0x20000>::new());
+        chip8
+            .set_megachip(Some(MegaChip::new(0x12000, 0x1e000)))
+            .unwrap();
+        chip8.load_program(&[
+            0x00, 0x11, 0x01, 0x00, 0x03, 0x00, 0x02, 0x03, 0x03, 0x01, 0x04, 0x01, 0xa3, 0x0c,
+            0xd0, 0x01, 0x08, 0x02, 0xa3, 0x0d, 0xd0, 0x01, 0x03, 0x04, 0x01, 0x01, 0xff, 0xff,
+            0xd0, 0x01, 0x06, 0x01,
+        ]);
+        // Red, blue and purple palette entries and two one pixel sprites
+        chip8.bus_mut().write_slice(
+            0x300,
+            &[
+                0xff, 0xff, 0x00, 0x00, 0xff, 0x00, 0x00, 0xff, 0xff, 0x7f, 0x00, 0x7f, 0x01, 0x02,
+            ],
+        );
+        for _ in 0..10 {
+            chip8.tick().unwrap();
+        }
+        // Blue over red at 50% gives purple
+        assert_eq!(chip8.bus_mut().read(0x12000), 0x03);
+        // A sprite and a sound past the end of the bus are cut short
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_megachip().unwrap().sample_rate(), None);
+    }
+
+    #[test]
+    fn megachip_font() {
+        let chip8 = get_fixture_no_keypad();
+        let mut chip8 = chip8.with_bus(Ram::<0x20000>::new());
+        chip8
+            .set_megachip(Some(MegaChip::new(0x12000, 0x1e000)))
+            .unwrap();
+        chip8.load_font(DEFAULT);
+        chip8.load_program(&[
+            0x00, 0x11, 0x01, 0x01, 0x23, 0x45, 0x60, 0x05, 0xf0, 0x29, 0xf0, 0x65,
+        ]);
+        for _ in 0..5 {
+            chip8.tick().unwrap();
+        }
+        // fx29 clears the high byte set by 01nn nnnn
+        assert_eq!(chip8.get_index(), 0x69);
+        assert_eq!(chip8.get_registers()[0], 0xf0);
+        assert_eq!(chip8.get_registers()[0], chip8.read_memory(0x69));
+    }
+
+    #[test]
+    fn megachip_long_sample() {
+        let chip8 = get_fixture_no_keypad();
+        let mut chip8 = chip8.with_bus(Ram::<0x20000>::new());
+        chip8
+            .set_megachip(Some(MegaChip::new(0x12000, 0x1e000)))
+            .unwrap();
+        chip8.load_program(&[0x00, 0x11, 0xa3, 0x00, 0x06, 0x00]);
+        // A looping sound of 65537 samples at 65535 samples per second
+        chip8
+            .bus_mut()
+            .write_slice(0x300, &[0xff, 0xff, 0x01, 0x00, 0x01, 0x00]);
+        chip8.bus_mut().write(0x306 + 65533, 0xbb);
+        chip8.bus_mut().write(0x306 + 65535, 0xaa);
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.next_sample(1), Some(0x00));
+        assert_eq!(chip8.next_sample(1), Some(0xaa));
+        assert_eq!(chip8.next_sample(1), Some(0xbb));
+    }
+
+    #[test]
+    fn chip8x() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.set_chip8x(Some(Chip8X::new()));
+        chip8.load_program(&[
+            0x60, 0x35, 0x61, 0x46, 0x50, 0x11, 0x62, 0x10, 0x63, 0x00, 0x64, 0x02, 0xb2, 0x40,
+            0x62, 0x03, 0x63, 0x0a, 0xb2, 0x42, 0x02, 0xa0, 0xe5, 0xf2, 0xe5, 0xf5,
+        ]);
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_registers()[0], 0x73);
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        let chip8x = chip8.get_chip8x().unwrap();
+        assert_eq!(chip8x.foreground(1, 3), 2);
+        assert_eq!(chip8x.foreground(2, 0), 1);
+        assert_eq!(chip8x.foreground(0, 4), 1);
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        let chip8x = chip8.get_chip8x().unwrap();
+        assert_eq!(chip8x.foreground(3, 11), 2);
+        assert_eq!(chip8x.foreground(3, 12), 1);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_chip8x().unwrap().background(), 1);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_program_counter(), 0x218);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_program_counter(), 0x21c);
+    }
+
+    #[test]
+    fn platform_cosmac_vip() {
+        let mut display = MockDisplay::<Rgb565>::new();
+        display.set_allow_overdraw(true);
+        display.set_allow_out_of_bounds_drawing(true);
+        let keypad = get_keypad_no_keys();
+        let platform = Platform::CosmacVip;
+        let mut chip8 =
+            Chip8::with_platform(display, keypad, MockRng {}, MockDelay::new(), platform);
+        assert_eq!(chip8.get_platform(), Some(Platform::CosmacVip));
+        assert_eq!(chip8.get_instruction_set(), InstructionSet::Chip8);
+        assert_eq!(chip8.get_memory_layout(), MemoryLayout::Vip);
+        assert_eq!(chip8.get_memory_map(), MemoryMap::COSMAC_VIP);
+        assert_eq!(chip8.read_memory(0x50), VIP.data()[0]);
+        chip8.load_program(&[
+            0x6f, 0x01, 0x60, 0x03, 0x61, 0x05, 0x80, 0x11, 0xa3, 0x00, 0xf1, 0x55, 0x80, 0x16,
+            0x00, 0xff,
+        ]);
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_registers()[0x0], 0x07);
+        assert_eq!(chip8.get_registers()[0xf], 0x00);
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.read_memory(0x301), 0x05);
+        assert_eq!(chip8.get_index(), 0x302);
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0x0], 0x02);
+        assert_eq!(chip8.get_registers()[0xf], 0x01);
+        // 00ff is a machine language call outside SUPER-CHIP
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_resolution(), Resolution::Standard);
+    }
+
+    #[test]
+    fn platform_super_chip() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.reset_platform(Platform::SuperChipModern);
+        chip8.load_program(&[
+            0x00, 0xff, 0xa3, 0x00, 0xd0, 0x11, 0x00, 0xfb, 0x60, 0x04, 0xd0, 0x11, 0xa3, 0x10,
+            0x60, 0x08, 0x61, 0x00, 0xd0, 0x10, 0xa3, 0x00, 0x60, 0x14, 0x61, 0x0f, 0xd0, 0x11,
+            0x60, 0x2a, 0xf0, 0x75, 0x60, 0x00, 0xf0, 0x85, 0x00, 0xfd,
+        ]);
+        chip8.write_memory(0x300, 0x80);
+        for address in 0x310..0x330 {
+            chip8.write_memory(address, 0xff);
+        }
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_resolution(), Resolution::Chip10);
+        for _ in 0..5 {
+            chip8.tick().unwrap();
+        }
+        // The pixel drawn at (0, 0) was scrolled to (4, 0)
+        assert_eq!(chip8.get_registers()[0xf], 1);
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_registers()[0xf], 0);
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        // The bottom right of the 16x16 sprite
+        assert_eq!(chip8.get_registers()[0xf], 1);
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_registers()[0x0], 0x2a);
+        assert_eq!(chip8.get_flags()[0x0], 0x2a);
+        assert_eq!(chip8.tick(), Err(Error::Exited { address: 0x224 }));
+        assert_eq!(chip8.tick(), Err(Error::Exited { address: 0x224 }));
+    }
+
+    #[test]
+    fn platform_xo_chip() {
+        let chip8 = get_fixture_no_keypad();
+        let mut chip8 = chip8.with_bus(Ram::<0x10000>::new());
+        chip8.reset_platform(Platform::XoChip);
+        chip8.load_program(&[
+            0xf0, 0x00, 0x12, 0x34, 0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x52, 0x02, 0x30, 0x01,
+            0xf0, 0x00, 0x00, 0x00, 0xf2, 0x01, 0xa3, 0x00, 0xd0, 0x11, 0xf1, 0x01, 0xd0, 0x11,
+            0xf2, 0x01, 0x00, 0xe0, 0xd0, 0x11, 0xf1, 0x01, 0xd0, 0x11,
+        ]);
+        chip8.write_memory(0x300, 0x80);
+        for _ in 0..5 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_index(), 0x1234);
+        assert_eq!(chip8.read_memory(0x1234), 0x03);
+        assert_eq!(chip8.read_memory(0x1236), 0x01);
+        // The skip steps over all four bytes of f000 nnnn
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_program_counter(), 0x212);
+        for _ in 0..5 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_registers()[0xf], 0);
+        // Clearing the second plane leaves the first
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_registers()[0xf], 0);
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        assert_eq!(chip8.get_registers()[0xf], 1);
+    }
+
+    #[test]
+    fn sprite_clip_and_wrap() {
+        let mut chip8 = get_fixture_no_keypad();
+        // Draw 0xff at (60, 0) then 0x80 at (0, 0)
+        let program = [
+            0x60, 0x3c, 0xa3, 0x00, 0xd0, 0x11, 0x60, 0x00, 0xa3, 0x01, 0xd0, 0x11,
+        ];
+        chip8.load_program(&program);
+        chip8.write_memory(0x300, 0xff);
+        chip8.write_memory(0x301, 0x80);
+        for _ in 0..6 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_registers()[0xf], 0);
+        let mut chip8 = chip8.with_bus(Ram::<0x10000>::new());
+        chip8.reset_platform(Platform::XoChip);
+        chip8.load_program(&program);
+        chip8.write_memory(0x300, 0xff);
+        chip8.write_memory(0x301, 0x80);
+        for _ in 0..6 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_registers()[0xf], 1);
+    }
+
+    #[test]
+    fn framebuffer() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0x60, 0x02, 0x61, 0x01, 0xa3, 0x00, 0xd0, 0x11]);
+        chip8.write_memory(0x300, 0xc0);
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        let framebuffer = *chip8.get_framebuffer();
+        assert_eq!((framebuffer.width(), framebuffer.height()), (64, 32));
+        assert_eq!(framebuffer.get(2, 1), 1);
+        assert_eq!(framebuffer.get(4, 1), 0);
+        assert_eq!(framebuffer.rows(0).nth(1), Some(0x3 << 124));
+        assert_eq!(framebuffer.line(1).filter(|&planes| planes != 0).count(), 2);
+        let mut display = MockDisplay::<Rgb565>::new();
+        display.set_allow_out_of_bounds_drawing(true);
+        Image::new(&framebuffer, Point::new(1, 1))
+            .draw(&mut display)
+            .unwrap();
+        assert_eq!(display.get_pixel(Point::new(3, 2)), Some(Rgb565::WHITE));
+        assert_eq!(display.get_pixel(Point::new(1, 1)), Some(Rgb565::BLACK));
+        assert_eq!(display.get_pixel(Point::new(0, 0)), None);
+        chip8.set_memory_layout(MemoryLayout::Vip);
+        chip8.write_memory(0xf00, 0x80);
+        assert_eq!(chip8.get_framebuffer().get(0, 0), 0);
+        chip8.sync_framebuffer();
+        assert_eq!(chip8.get_framebuffer().get(0, 0), 1);
+    }
+
+    #[test]
+    fn framebuffer_size() {
+        assert_eq!(
+            size_of::<Framebuffer<CHIP8_SIZE>>(),
+            size_of::<Framebuffer>() - 1792
+        );
+        // Draw at (2, 1), switch to 128x64 and draw at (2, 40)
+        let program = [
+            0x60, 0x02, 0x61, 0x01, 0xa3, 0x00, 0xd0, 0x11, 0x00, 0xff, 0x61, 0x28, 0xd0, 0x11,
+        ];
+        let mut chip8 = get_fixture_no_keypad().with_framebuffer::<CHIP8_SIZE>();
+        chip8.set_instruction_set(InstructionSet::SuperChip);
+        chip8.load_program(&program);
+        chip8.write_memory(0x300, 0xc0);
+        for _ in 0..7 {
+            chip8.tick().unwrap();
+        }
+        // Only the top 16 lines fit at 128x64
+        let framebuffer = chip8.get_framebuffer();
+        assert_eq!((framebuffer.width(), framebuffer.height()), (128, 64));
+        assert_eq!(framebuffer.get(2, 1), 1);
+        assert_eq!(framebuffer.get(2, 40), 0);
+
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.set_instruction_set(InstructionSet::SuperChip);
+        chip8.load_program(&program);
+        chip8.write_memory(0x300, 0xc0);
+        for _ in 0..7 {
+            chip8.tick().unwrap();
+        }
+        let framebuffer = chip8.get_framebuffer();
+        assert_eq!(framebuffer.get(2, 1), 1);
+        assert_eq!(framebuffer.get(2, 40), 1);
+    }
+
+    #[test]
+    fn redraw_and_release() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0x60, 0x02, 0x61, 0x01, 0xa3, 0x00, 0xd0, 0x11]);
+        chip8.write_memory(0x300, 0x80);
+        for _ in 0..4 {
+            chip8.tick().unwrap();
+        }
+        chip8.display_mut().clear(Rgb565::RED).unwrap();
+        chip8.redraw();
+        let parts = chip8.release();
+        let display = parts.display;
+        assert_eq!(parts.bus.as_slice().unwrap()[0x300], 0x80);
+        assert_eq!(display.get_pixel(Point::new(2, 1)), Some(Rgb565::WHITE));
+        assert_eq!(display.get_pixel(Point::new(3, 1)), Some(Rgb565::BLACK));
+        assert_eq!(display.get_pixel(Point::new(0, 40)), Some(Rgb565::RED));
+    }
+
+    #[test]
+    fn soft_and_hard_reset() {
+        let mut chip8 = get_fixture_no_keypad();
+        // Overwrite the start of the program with v0
+        chip8.load_rom(&[0x60, 0x05, 0xa2, 0x00, 0xf0, 0x55]);
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.read_memory(0x200), 0x05);
+        chip8.write_memory(0x300, 0x2a);
+        chip8.soft_reset().unwrap();
+        assert_eq!(chip8.get_program_counter(), 0x200);
+        assert_eq!(chip8.get_registers()[0x0], 0x00);
+        assert_eq!(chip8.read_memory(0x200), 0x60);
+        assert_eq!(chip8.read_memory(0x300), 0x2a);
+        assert_eq!(chip8.read_memory(0x50), DEFAULT.data()[0]);
+        chip8.set_power_on_memory(PowerOnMemory::Ones);
+        chip8.hard_reset().unwrap();
+        assert_eq!(chip8.read_memory(0x300), 0xff);
+        assert_eq!(chip8.read_memory(0x200), 0x60);
+        assert_eq!(chip8.read_memory(0x50), DEFAULT.data()[0]);
+        chip8.set_power_on_memory(PowerOnMemory::Random(1));
+        chip8.hard_reset().unwrap();
+        let first = chip8.read_memory(0x300);
+        assert!((0x300..0x310).any(|address| chip8.read_memory(address) != first));
+        chip8.hard_reset().unwrap();
+        assert_eq!(chip8.read_memory(0x300), first);
+
+        // A program that is not 'static is given on every reset
+        let program = vec![0x60, 0x07];
+        chip8.set_power_on_memory(PowerOnMemory::Zeros);
+        chip8.hard_reset_with(&program);
+        assert_eq!(chip8.read_memory(0x201), 0x07);
+        assert_eq!(chip8.read_memory(0x202), 0x00);
+        chip8.write_memory(0x201, 0x08);
+        chip8.soft_reset_with(&program);
+        assert_eq!(chip8.read_memory(0x201), 0x07);
+        assert_eq!(chip8.soft_reset(), Err(Error::NoProgram));
+        assert_eq!(chip8.read_memory(0x201), 0x07);
+    }
+
+    #[test]
+    fn reset_clears_display() {
+        let mut chip8 = get_fixture_no_keypad();
+        chip8.load_program(&[0xa3, 0x00, 0xd0, 0x01]);
+        chip8.write_memory(0x300, 0x80);
+        chip8.tick().unwrap();
+        chip8.tick().unwrap();
+        let pixel = Point::new(0, 0);
+        assert_eq!(chip8.display_mut().get_pixel(pixel), Some(Rgb565::WHITE));
+        chip8.reset();
+        assert_eq!(chip8.display_mut().get_pixel(pixel), Some(Rgb565::BLACK));
+    }
+
+    #[test]
+    fn seeded_rng() {
+        let program = [0xc0, 0xff, 0xc1, 0xff, 0xc2, 0xff];
+        let run = |seed| {
+            let mut display = MockDisplay::<Rgb565>::new();
+            display.set_allow_overdraw(true);
+            let keypad = get_keypad_no_keys();
+            let mut chip8 = Chip8::with_seed(display, keypad, MockDelay::new(), seed);
+            chip8.load_program(&program);
+            for _ in 0..3 {
+                chip8.tick().unwrap();
+            }
+            (chip8.get_registers(), chip8.get_rng_state())
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7).0, run(8).0);
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut display = MockDisplay::<Rgb565>::new();
+        display.set_allow_overdraw(true);
+        let mut chip8 = Chip8::with_seed(display, get_keypad_no_keys(), MockDelay::new(), 3);
+        chip8.load_program(&[0xc0, 0xff, 0xa3, 0x00, 0xd1, 0x11, 0xc1, 0xff]);
+        chip8.write_memory(0x300, 0x80);
+        chip8.tick().unwrap();
+        let snapshot = chip8.snapshot();
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        let registers = chip8.get_registers();
+        assert_eq!(chip8.get_framebuffer().get(0, 0), 1);
+        chip8.restore(&snapshot);
+        assert_eq!(chip8.get_program_counter(), 0x202);
+        assert_eq!(chip8.get_registers()[0x1], 0);
+        assert_eq!(chip8.get_framebuffer().get(0, 0), 0);
+        for _ in 0..3 {
+            chip8.tick().unwrap();
+        }
+        assert_eq!(chip8.get_registers(), registers);
+    }
+
+    #[test]
+    fn keypad_error() {
+        struct Broken;
+
+        impl KeySource for Broken {
+            type Error = ();
+
+            fn scan<D>(&mut self, _delay: &mut D) -> Result<u16, ()>
+            where
+                D: DelayMs<u32>,
+            {
+                Err(())
+            }
+        }
+
+        let mut chip8 = Chip8::new(
+            MockDisplay::<Rgb565>::new(),
+            Broken,
+            MockRng {},
+            MockDelay::new(),
+        );
+        chip8.load_program(&[0xe0, 0x9e]);
+        assert_eq!(chip8.tick(), Err(Error::KeyPad { address: 0x200 }));
+        assert_eq!(chip8.get_program_counter(), 0x200);
+        assert_eq!(chip8.get_error(), None);
+    }
+}
+
+#[cfg(test)]
+mod keypad {
+    use chip8::keypad::{
+        AxisCalibration, Button, ButtonMap, Controller, Expander, ExpanderKeyPad, GamePad,
+        HidKeyboard, InvalidReport, Joystick, JoystickConfig, KeyPad, KeyPadConfig, KeySource,
+        Polarity, Ps2Keyboard, SharedKeys, ShiftRegisterKeyPad, TouchController, TouchKeyPad,
+        VirtualKeyPad,
+    };
+    use embedded_graphics::{
+        mock_display::MockDisplay, pixelcolor::Rgb565, prelude::*, primitives::Rectangle,
+    };
+    use embedded_hal_mock::{
+        adc::{Mock as MockAdc, MockChan0, MockChan1, Transaction as AdcTransaction},
+        delay::MockNoop as MockDelay,
+        i2c::{Mock as MockI2c, Transaction as I2cTransaction},
+        pin::{Mock as MockPin, State as PinState, Transaction as PinTransaction},
+        MockError,
+    };
+    use std::io::ErrorKind;
+
+    #[test]
+    fn new_releases_rows() {
+        let expect = [PinTransaction::set(PinState::High)];
+        let keypad = KeyPad::<_, _, 2, 1>::new(
+            [MockPin::new(&expect), MockPin::new(&expect)],
+            [MockPin::new(&[])],
+        )
+        .unwrap();
+        let (mut rows, mut cols) = keypad.release();
+        rows.iter_mut().for_each(|r| r.done());
+        cols.iter_mut().for_each(|c| c.done());
+    }
+
+    #[test]
+    fn new_pin_error() {
+        let err = MockError::Io(ErrorKind::NotConnected);
+        let keypad = KeyPad::<_, _, 1, 1>::new(
+            [MockPin::new(&[
+                PinTransaction::set(PinState::High).with_error(err.clone())
+            ])],
+            [MockPin::new(&[])],
+        );
+        assert!(keypad.is_err());
+    }
+
+    #[test]
+    fn get_active_low() {
+        let config = KeyPadConfig {
+            debounce_samples: 2,
+            ..KeyPadConfig::default()
+        };
+        let row0 = [
+            PinTransaction::set(PinState::High),
+            PinTransaction::set(PinState::Low),
+            PinTransaction::set(PinState::High),
+        ];
+        let row1 = row0.clone();
+        let col0 = [
+            PinTransaction::get(PinState::High),
+            PinTransaction::get(PinState::Low),
+            PinTransaction::get(PinState::Low),
+            PinTransaction::get(PinState::Low),
+        ];
+        let col1 = [
+            PinTransaction::get(PinState::Low),
+            PinTransaction::get(PinState::High),
+            PinTransaction::get(PinState::High),
+        ];
+        let mut keypad = KeyPad::<_, _, 2, 2>::with_config(
+            [MockPin::new(&row0), MockPin::new(&row1)],
+            [MockPin::new(&col0), MockPin::new(&col1)],
+            config,
+        )
+        .unwrap();
+        assert_eq!(keypad.get(&mut MockDelay::new()).unwrap(), (true, 2));
+        let (mut rows, mut cols) = keypad.release();
+        rows.iter_mut().for_each(|r| r.done());
+        cols.iter_mut().for_each(|c| c.done());
+    }
+
+    #[test]
+    fn get_active_high() {
+        let config = KeyPadConfig {
+            polarity: Polarity::ActiveHigh,
+            debounce_samples: 0,
+            ..KeyPadConfig::default()
+        };
+        let row = [
+            PinTransaction::set(PinState::Low),
+            PinTransaction::set(PinState::High),
+            PinTransaction::set(PinState::Low),
+        ];
+        let col0 = [PinTransaction::get(PinState::Low)];
+        let col1 = [PinTransaction::get(PinState::High)];
+        let mut keypad = KeyPad::<_, _, 1, 2>::with_config(
+            [MockPin::new(&row)],
+            [MockPin::new(&col0), MockPin::new(&col1)],
+            config,
+        )
+        .unwrap();
+        assert_eq!(keypad.get(&mut MockDelay::new()).unwrap(), (true, 1));
+    }
+
+    #[test]
+    fn scan_matrix() {
+        let config = KeyPadConfig {
+            debounce_samples: 0,
+            ..KeyPadConfig::default()
+        };
+        let row = [
+            PinTransaction::set(PinState::High),
+            PinTransaction::set(PinState::Low),
+            PinTransaction::set(PinState::High),
+        ];
+        let col = [
+            PinTransaction::get(PinState::Low),
+            PinTransaction::get(PinState::Low),
+        ];
+        let mut keypad = KeyPad::<_, _, 2, 1>::with_config(
+            [MockPin::new(&row), MockPin::new(&row)],
+            [MockPin::new(&col)],
+            config,
+        )
+        .unwrap();
+        assert_eq!(keypad.scan(&mut MockDelay::new()).unwrap(), 0b11);
+    }
+
+    #[test]
+    fn shift_register() {
+        let latch = [
+            PinTransaction::set(PinState::High),
+            PinTransaction::set(PinState::Low),
+            PinTransaction::set(PinState::High),
+        ];
+        let mut clock = vec![PinTransaction::set(PinState::Low)];
+        let mut data = vec![];
+        for i in 0..16 {
+            let state = if i == 0 || i == 0xa {
+                PinState::Low
+            } else {
+                PinState::High
+            };
+            data.push(PinTransaction::get(state));
+            clock.push(PinTransaction::set(PinState::High));
+            clock.push(PinTransaction::set(PinState::Low));
+        }
+        let mut keypad = ShiftRegisterKeyPad::new(
+            MockPin::new(&latch),
+            MockPin::new(&clock),
+            MockPin::new(&data),
+        )
+        .unwrap();
+        assert_eq!(keypad.scan(&mut MockDelay::new()).unwrap(), 0x0401);
+        let (mut latch, mut clock, mut data) = keypad.release();
+        latch.done();
+        clock.done();
+        data.done();
+    }
+
+    #[test]
+    fn expander_pcf8574() {
+        let address = 0x20;
+        let expect = [
+            I2cTransaction::write(address, vec![0xff]),
+            I2cTransaction::write(address, vec![0xfe]),
+            I2cTransaction::read(address, vec![0xfe]),
+            I2cTransaction::write(address, vec![0xfd]),
+            I2cTransaction::read(address, vec![0x7d]),
+            I2cTransaction::write(address, vec![0xfb]),
+            I2cTransaction::read(address, vec![0xfb]),
+            I2cTransaction::write(address, vec![0xf7]),
+            I2cTransaction::read(address, vec![0xe7]),
+            I2cTransaction::write(address, vec![0xff]),
+        ];
+        let mut keypad =
+            ExpanderKeyPad::new(MockI2c::new(&expect), Expander::Pcf8574, address).unwrap();
+        assert_eq!(keypad.scan(&mut MockDelay::new()).unwrap(), 0x1080);
+        keypad.release().done();
+    }
+
+    #[test]
+    fn expander_mcp23017() {
+        let address = 0x27;
+        let mut expect = vec![
+            I2cTransaction::write(address, vec![0x00, 0xf0]),
+            I2cTransaction::write(address, vec![0x0c, 0xf0]),
+            I2cTransaction::write(address, vec![0x14, 0xff]),
+        ];
+        for row in 0..4 {
+            let port = !(1u8 << row);
+            let response = if row == 2 { port & !0x20 } else { port };
+            expect.push(I2cTransaction::write(address, vec![0x14, port]));
+            expect.push(I2cTransaction::write_read(
+                address,
+                vec![0x12],
+                vec![response],
+            ));
+        }
+        expect.push(I2cTransaction::write(address, vec![0x14, 0xff]));
+        let mut keypad =
+            ExpanderKeyPad::new(MockI2c::new(&expect), Expander::Mcp23017, address).unwrap();
+        assert_eq!(keypad.scan(&mut MockDelay::new()).unwrap(), 0x0200);
+        keypad.release().done();
+    }
+
+    /// Builds the pin transactions for one controller read where
+    /// the buttons in `pressed` read low
+    fn gamepad_pins(bits: usize, pressed: &[usize]) -> (MockPin, MockPin, MockPin) {
+        let latch = [
+            PinTransaction::set(PinState::Low),
+            PinTransaction::set(PinState::High),
+            PinTransaction::set(PinState::Low),
+        ];
+        let mut clock = vec![PinTransaction::set(PinState::Low)];
+        let mut data = vec![];
+        for i in 0..bits {
+            let state = if pressed.contains(&i) {
+                PinState::Low
+            } else {
+                PinState::High
+            };
+            data.push(PinTransaction::get(state));
+            clock.push(PinTransaction::set(PinState::High));
+            clock.push(PinTransaction::set(PinState::Low));
+        }
+        (
+            MockPin::new(&latch),
+            MockPin::new(&clock),
+            MockPin::new(&data),
+        )
+    }
+
+    #[test]
+    fn gamepad_nes() {
+        // A and Up
+        let (latch, clock, data) = gamepad_pins(8, &[0, 4]);
+        let mut gamepad = GamePad::new(latch, clock, data, Controller::Nes).unwrap();
+        assert_eq!(gamepad.scan(&mut MockDelay::new()).unwrap(), 0x0024);
+        let (mut latch, mut clock, mut data) = gamepad.release();
+        latch.done();
+        clock.done();
+        data.done();
+    }
+
+    #[test]
+    fn gamepad_snes_mapping() {
+        // Y, Right and R
+        let (latch, clock, data) = gamepad_pins(16, &[1, 7, 11]);
+        let mut mapping = ButtonMap::default();
+        mapping.set(Button::Y, Some(0xf));
+        mapping.set(Button::R, None);
+        let mut gamepad =
+            GamePad::with_mapping(latch, clock, data, Controller::Snes, mapping).unwrap();
+        assert_eq!(gamepad.scan(&mut MockDelay::new()).unwrap(), 0x8040);
+        let (mut latch, mut clock, mut data) = gamepad.release();
+        latch.done();
+        clock.done();
+        data.done();
+    }
+
+    /// Feeds a byte as a PS/2 frame, optionally with a bad parity bit
+    fn ps2_byte(keyboard: &mut Ps2Keyboard<MockPin>, byte: u8, good_parity: bool) {
+        keyboard.push_bit(false);
+        for i in 0..8 {
+            keyboard.push_bit(byte & (1 << i) != 0);
+        }
+        keyboard.push_bit((byte.count_ones() & 1 == 0) == good_parity);
+        keyboard.push_bit(true);
+    }
+
+    #[test]
+    fn ps2_make_break() {
+        let mut keyboard = Ps2Keyboard::new(MockPin::new(&[]));
+        // W and 4 held
+        ps2_byte(&mut keyboard, 0x1d, true);
+        ps2_byte(&mut keyboard, 0x25, true);
+        assert_eq!(keyboard.scan(&mut MockDelay::new()).unwrap(), 0x1020);
+        // W released
+        ps2_byte(&mut keyboard, 0xf0, true);
+        ps2_byte(&mut keyboard, 0x1d, true);
+        assert_eq!(keyboard.keys(), 0x1000);
+        // Extended keys are ignored
+        ps2_byte(&mut keyboard, 0xe0, true);
+        ps2_byte(&mut keyboard, 0x22, true);
+        assert_eq!(keyboard.keys(), 0x1000);
+    }
+
+    #[test]
+    fn ps2_parity_error() {
+        let mut keyboard = Ps2Keyboard::new(MockPin::new(&[]));
+        ps2_byte(&mut keyboard, 0x22, false);
+        assert_eq!(keyboard.keys(), 0);
+        ps2_byte(&mut keyboard, 0x22, true);
+        assert_eq!(keyboard.keys(), 0x0001);
+    }
+
+    #[test]
+    fn ps2_on_falling_edge() {
+        // 0x22 with its start, parity and stop bits
+        let bits = [0, 0, 1, 0, 0, 0, 1, 0, 0, 1, 1];
+        let expect: Vec<_> = bits
+            .iter()
+            .map(|&b| {
+                PinTransaction::get(if b == 1 {
+                    PinState::High
+                } else {
+                    PinState::Low
+                })
+            })
+            .collect();
+        let mut keyboard = Ps2Keyboard::new(MockPin::new(&expect));
+        for _ in bits {
+            keyboard.on_falling_edge().unwrap();
+        }
+        assert_eq!(keyboard.keys(), 0x0001);
+        keyboard.release().done();
+    }
+
+    #[test]
+    fn shared_keys() {
+        static KEYS: SharedKeys = SharedKeys::new();
+        KEYS.store(0x0810);
+        assert_eq!((&KEYS).scan(&mut MockDelay::new()).unwrap(), 0x0810);
+    }
+
+    #[test]
+    fn hid_report() {
+        let mut keyboard = HidKeyboard::new();
+        // Left shift, 1 and V
+        assert_eq!(
+            keyboard
+                .feed(&[0x02, 0x00, 0x1e, 0x19, 0, 0, 0, 0])
+                .unwrap(),
+            0x8002
+        );
+        // Unmapped keys are ignored
+        assert_eq!(
+            keyboard
+                .feed(&[0x00, 0x00, 0x2c, 0x19, 0, 0, 0, 0])
+                .unwrap(),
+            0x8000
+        );
+        assert_eq!(keyboard.scan(&mut MockDelay::new()).unwrap(), 0x8000);
+    }
+
+    #[test]
+    fn hid_rollover() {
+        let mut keyboard = HidKeyboard::with_layout([
+            0x27, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x04, 0x05, 0x06, 0x07,
+            0x08, 0x09,
+        ]);
+        assert_eq!(keyboard.feed(&[0, 0, 0x27, 0, 0, 0, 0, 0]).unwrap(), 0x0001);
+        assert_eq!(keyboard.feed(&[0, 0, 1, 1, 1, 1, 1, 1]).unwrap(), 0x0001);
+        assert_eq!(keyboard.feed(&[0, 0, 0, 0, 0, 0, 0, 0]).unwrap(), 0);
+        assert_eq!(keyboard.feed(&[0, 0, 0x27]), Err(InvalidReport));
+    }
+
+    #[test]
+    fn joystick_directions() {
+        let expect = [
+            // Centered
+            AdcTransaction::read(0, 2047u16),
+            AdcTransaction::read(1, 2047u16),
+            // Full left and slightly down
+            AdcTransaction::read(0, 0u16),
+            AdcTransaction::read(1, 2300u16),
+            // Full right and full up
+            AdcTransaction::read(0, 4095u16),
+            AdcTransaction::read(1, 0u16),
+        ];
+        let mut joystick = Joystick::new(
+            MockAdc::new(&expect),
+            MockChan0,
+            MockChan1,
+            JoystickConfig::default(),
+        );
+        let mut delay = MockDelay::new();
+        assert_eq!(joystick.scan(&mut delay).unwrap(), 0);
+        assert_eq!(joystick.scan(&mut delay).unwrap(), 1 << 4);
+        assert_eq!(joystick.scan(&mut delay).unwrap(), (1 << 2) | (1 << 6));
+        joystick.release().0.done();
+    }
+
+    #[test]
+    fn joystick_reversed_calibration() {
+        let expect = [
+            AdcTransaction::read(0, 0u16),
+            AdcTransaction::read(1, 2047u16),
+            AdcTransaction::read(0, 4095u16),
+            AdcTransaction::read(1, 2047u16),
+        ];
+        let reversed = AxisCalibration {
+            min: 4095,
+            center: 2047,
+            max: 0,
+            inverted: false,
+        };
+        let config = JoystickConfig {
+            x: reversed,
+            ..JoystickConfig::default()
+        };
+        let mut joystick = Joystick::new(MockAdc::new(&expect), MockChan0, MockChan1, config);
+        let mut delay = MockDelay::new();
+        assert_eq!(joystick.scan(&mut delay).unwrap(), 1 << 6);
+        assert_eq!(joystick.scan(&mut delay).unwrap(), 1 << 4);
+        joystick.release().0.done();
+    }
+
+    #[test]
+    fn joystick_hysteresis_and_fire() {
+        let expect = [
+            AdcTransaction::read(0, 2047u16),
+            AdcTransaction::read(1, 3000u16),
+            // Just inside the dead zone but within the hysteresis band
+            AdcTransaction::read(0, 2047u16),
+            AdcTransaction::read(1, 2600u16),
+            // Below the hysteresis band
+            AdcTransaction::read(0, 2047u16),
+            AdcTransaction::read(1, 2500u16),
+        ];
+        let fire = [
+            PinTransaction::get(PinState::Low),
+            PinTransaction::get(PinState::High),
+            PinTransaction::get(PinState::High),
+        ];
+        let mut joystick = Joystick::new(
+            MockAdc::new(&expect),
+            MockChan0,
+            MockChan1,
+            JoystickConfig::default(),
+        )
+        .with_fire(MockPin::new(&fire));
+        let mut delay = MockDelay::new();
+        assert_eq!(joystick.scan(&mut delay).unwrap(), (1 << 8) | (1 << 5));
+        assert_eq!(joystick.scan(&mut delay).unwrap(), 1 << 8);
+        assert_eq!(joystick.scan(&mut delay).unwrap(), 0);
+    }
+
+    struct MockTouch(Vec<Point>);
+
+    impl TouchController for MockTouch {
+        type Error = ();
+
+        fn touches(&mut self, points: &mut [Point]) -> Result<usize, ()> {
+            let count = self.0.len().min(points.len());
+            points[..count].copy_from_slice(&self.0[..count]);
+            Ok(count)
+        }
+    }
+
+    #[test]
+    fn virtual_keypad_key_at() {
+        let keypad = VirtualKeyPad::new(Rectangle::new(Point::new(0, 32), Size::new(64, 32)));
+        assert_eq!(keypad.key_at(Point::new(0, 0)), None);
+        assert_eq!(keypad.key_at(Point::new(0, 32)), Some(0x1));
+        assert_eq!(keypad.key_at(Point::new(63, 32)), Some(0xc));
+        assert_eq!(keypad.key_at(Point::new(20, 63)), Some(0x0));
+        assert_eq!(keypad.key_at(Point::new(64, 63)), None);
+    }
+
+    #[test]
+    fn virtual_keypad_draw() {
+        let keypad = VirtualKeyPad::new(Rectangle::new(Point::new(0, 0), Size::new(64, 64)));
+        let mut display = MockDisplay::<Rgb565>::new();
+        display.set_allow_overdraw(true);
+        display.set_allow_out_of_bounds_drawing(true);
+        keypad.draw(&mut display, 1 << 0x5).unwrap();
+        // Key 5 is the second key of the second row
+        assert_eq!(display.get_pixel(Point::new(17, 17)), Some(Rgb565::WHITE));
+        assert_eq!(display.get_pixel(Point::new(1, 1)), Some(Rgb565::BLACK));
+    }
+
+    #[test]
+    fn touch_keypad_multi_touch() {
+        let keypad = VirtualKeyPad::new(Rectangle::new(Point::new(0, 0), Size::new(40, 40)));
+        let touch = MockTouch(vec![
+            Point::new(5, 5),
+            Point::new(35, 35),
+            Point::new(50, 5),
+        ]);
+        let mut touch = TouchKeyPad::new(touch, keypad);
+        assert_eq!(
+            touch.scan(&mut MockDelay::new()).unwrap(),
+            (1 << 0x1) | (1 << 0xf)
+        );
+    }
+}
+
+#[cfg(test)]
+mod fonts {
+    use chip8::fonts::{self, FONTS, SUPER_CHIP_BIG, XO_CHIP_BIG};
+
+    #[test]
+    fn glyphs() {
+        for font in FONTS {
+            assert_eq!(
+                font.data().len(),
+                font.glyphs() as usize * font.height() as usize
+            );
+        }
+        assert_eq!(SUPER_CHIP_BIG.glyphs(), 10);
+        assert_eq!(XO_CHIP_BIG.glyphs(), 16);
+        assert_eq!(fonts::DEFAULT.glyphs(), 16);
+    }
+
+    #[test]
+    fn find() {
+        assert_eq!(fonts::find("ETI-660"), Some(fonts::ETI_660));
+        assert_eq!(fonts::find("CHIP-99"), None);
+    }
+}
+
+#[cfg(test)]
+mod instruction {
+    use chip8::{Instruction, InstructionSet};
+
+    #[test]
+    fn decode() {
+        assert_eq!(Instruction::decode((0x00, 0xe0)), Some(Instruction::Cls));
+        assert_eq!(Instruction::decode((0x00, 0xee)), Some(Instruction::Ret));
+        assert_eq!(
+            Instruction::decode((0x01, 0x20)),
+            Some(Instruction::SysCall(0x120))
+        );
+        assert_eq!(
+            Instruction::decode((0xd1, 0x2f)),
+            Some(Instruction::Drw {
+                x: 0x1,
+                y: 0x2,
+                n: 0xf
+            })
+        );
+        assert_eq!(Instruction::decode((0x51, 0x21)), None);
+        assert_eq!(Instruction::decode((0x91, 0x2f)), None);
+        assert_eq!(Instruction::decode((0xe1, 0x9f)), None);
+        assert_eq!(Instruction::decode((0xf1, 0x2a)), None);
+        assert_eq!(
+            Instruction::decode((0x00, 0xc4)),
+            Some(Instruction::ScrollDown(4))
+        );
+        assert_eq!(
+            Instruction::decode((0xf3, 0x01)),
+            Some(Instruction::Plane(3))
+        );
+    }
+
+    #[test]
+    fn instruction_set() {
+        assert!(InstructionSet::Chip8.supports(Instruction::Cls));
+        assert!(!InstructionSet::Chip8.supports(Instruction::High));
+        assert!(InstructionSet::SuperChip.supports(Instruction::High));
+        assert!(!InstructionSet::SuperChip.supports(Instruction::LdILong));
+        assert!(InstructionSet::XoChip.supports(Instruction::LdILong));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for word in 0..=u16::MAX {
+            let opcode = ((word >> 8) as u8, word as u8);
+            if let Some(instruction) = Instruction::decode(opcode) {
+                assert_eq!(instruction.encode(), opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn display() {
+        let instruction = Instruction::LdByte { x: 0x1, nn: 0x2a };
+        assert_eq!(instruction.to_string(), "LD V1, 0x2A");
+        assert_eq!(Instruction::Jp(0x200).to_string(), "JP 0x200");
+    }
+}
+
+#[cfg(test)]
+mod movie {
+    use chip8::keypad::KeySource;
+    use chip8::movie::{MovieError, MovieHeader, Player, Recorder};
+    use chip8::platform::Platform;
+    use chip8::Chip8;
+    use embedded_graphics::mock_display::MockDisplay;
+    use embedded_graphics::pixelcolor::Rgb565;
+    use embedded_hal::blocking::delay::DelayMs;
+    use embedded_hal_mock::delay::MockNoop as MockDelay;
+
+    /// Holds key 5 on every third scan
+    struct Scripted {
+        scans: u32,
+    }
+
+    impl KeySource for Scripted {
+        type Error = ();
+
+        fn scan<D>(&mut self, _delay: &mut D) -> Result<u16, ()>
+        where
+            D: DelayMs<u32>,
+        {
+            self.scans += 1;
+            Ok(if self.scans.is_multiple_of(3) {
+                1 << 5
+            } else {
+                0
+            })
+        }
+    }
+
+    // Draws a pixel at a random place each pass while key 5 is held
+    const PROGRAM: [u8; 14] = [
+        0xa3, 0x00, 0xc0, 0x3f, 0xc1, 0x1f, 0x62, 0x05, 0xe2, 0xa1, 0xd0, 0x11, 0x12, 0x02,
+    ];
+
+    fn display() -> MockDisplay<Rgb565> {
+        let mut display = MockDisplay::new();
+        display.set_allow_overdraw(true);
+        display
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let header = MovieHeader {
+            platform: Some(Platform::Chip48),
+            seed: 99,
+        };
+        let mut buffer = [0; 1024];
+        let recorder = Recorder::new(Scripted { scans: 0 }, &mut buffer, header).unwrap();
+        let mut chip8 = Chip8::with_seed(display(), recorder, MockDelay::new(), header.seed);
+        chip8.reset_platform(Platform::Chip48);
+        chip8.load_program(&PROGRAM);
+        chip8.write_memory(0x300, 0x80);
+        for _ in 0..20 {
+            chip8.keypad_mut().next_frame();
+            for _ in 0..10 {
+                chip8.tick().unwrap();
+            }
+        }
+        let recorded = *chip8.get_framebuffer();
+        let recorder = chip8.release().keypad;
+        assert!(!recorder.overflowed());
+        let (_, length) = recorder.release();
+
+        let player = Player::new(&buffer[..length]).unwrap();
+        assert_eq!(player.header(), header);
+        let mut chip8 = Chip8::with_seed(display(), player, MockDelay::new(), header.seed);
+        chip8.reset_platform(header.platform.unwrap());
+        chip8.load_program(&PROGRAM);
+        chip8.write_memory(0x300, 0x80);
+        for _ in 0..20 {
+            chip8.keypad_mut().next_frame();
+            for _ in 0..10 {
+                chip8.tick().unwrap();
+            }
+        }
+        assert!(chip8.keypad_mut().finished());
+        assert!(chip8.get_framebuffer().rows(0).any(|row| row != 0));
+        assert_eq!(*chip8.get_framebuffer(), recorded);
+    }
+
+    #[test]
+    fn bad_data() {
+        assert_eq!(Player::new(b"C8").err(), Some(MovieError::BadMagic));
+        assert_eq!(Player::new(b"C8MV").err(), Some(MovieError::Truncated));
+        let mut buffer = [0; 18];
+        let header = MovieHeader {
+            platform: None,
+            seed: 1,
+        };
+        Recorder::new(Scripted { scans: 0 }, &mut buffer, header).unwrap();
+        assert_eq!(Player::new(&buffer[..14]).unwrap().header(), header);
+        // A frame count with no scan or keys after it
+        buffer[14] = 0x01;
+        assert_eq!(
+            Player::new(&buffer[..15]).err(),
+            Some(MovieError::Truncated)
+        );
+        buffer[5] = 0x10;
+        assert_eq!(
+            Player::new(&buffer[..14]).err(),
+            Some(MovieError::UnknownPlatform(0x10))
+        );
+        let mut small = [0; 4];
+        assert_eq!(
+            Recorder::new(Scripted { scans: 0 }, &mut small, header).err(),
+            Some(MovieError::BufferFull)
+        );
+        // Two events u32::MAX frames apart
+        let mut long = [0; 30];
+        Recorder::new(Scripted { scans: 0 }, &mut long, header).unwrap();
+        for start in [14, 22] {
+            long[start..start + 5].copy_from_slice(&[0xff, 0xff, 0xff, 0xff, 0x0f]);
+        }
+        assert_eq!(Player::new(&long[..22]).unwrap().frame(), 0);
+        assert_eq!(Player::new(&long).err(), Some(MovieError::TooLong));
+    }
+}
+
+#[cfg(test)]
+mod rng {
+    use chip8::rng::Xorshift;
+    use rand::RngCore;
+
+    #[test]
+    fn repeatable() {
+        let mut first = Xorshift::new(1234);
+        let mut second = Xorshift::new(1234);
+        for _ in 0..8 {
+            assert_eq!(first.next_u32(), second.next_u32());
+        }
+        let state = first.state();
+        let expected = first.next_u64();
+        second.set_state(state);
+        assert_eq!(second.next_u64(), expected);
+        second.seed(1234);
+        assert_eq!(second, Xorshift::new(1234));
+    }
+
+    #[test]
+    fn zero_seed() {
+        let mut rng = Xorshift::new(0);
+        assert_ne!(rng.next_u64(), 0);
+        let mut bytes = [0; 11];
+        rng.fill_bytes(&mut bytes);
+        assert!(bytes.iter().any(|&byte| byte != 0));
+    }
+}
+
+#[cfg(test)]
+mod bus {
+    use chip8::bus::{Bus, MappedBus, Ram, SpiRam, SpiRamError};
+    use embedded_hal_mock::{
+        pin::{Mock as MockPin, State as PinState, Transaction as PinTransaction},
+        spi::{Mock as MockSpi, Transaction as SpiTransaction},
+        MockError,
+    };
+    use std::io::ErrorKind;
+
+    /// A peripheral that counts reads and remembers the last write
+    struct Counter {
+        reads: u8,
+        written: u8,
+    }
+
+    impl Bus for Counter {
+        fn len(&self) -> usize {
+            2
+        }
+
+        fn read(&mut self, address: usize) -> u8 {
+            self.reads += 1;
+            if address == 0 {
+                self.reads
+            } else {
+                self.written
+            }
+        }
+
+        fn write(&mut self, _address: usize, value: u8) {
+            self.written = value;
+        }
+    }
+
+    #[test]
+    fn ram() {
+        let mut ram = Ram::<16>::new();
+        ram.write_slice(4, &[1, 2, 3]);
+        assert_eq!(ram.read(5), 2);
+        ram.fill(0xff);
+        assert_eq!(ram.as_slice(), Some(&[0xff; 16][..]));
+    }
+
+    #[test]
+    fn mapped() {
+        let counter = Counter {
+            reads: 0,
+            written: 0,
+        };
+        let mut bus = MappedBus::new(Ram::<4096>::new(), 0xf00, counter);
+        bus.write(0xeff, 0x11);
+        bus.write(0xf01, 0x22);
+        assert_eq!(bus.read(0xeff), 0x11);
+        assert_eq!(bus.read(0xf00), 1);
+        assert_eq!(bus.read(0xf01), 0x22);
+        assert_eq!(bus.read(0xf02), 0);
+        assert_eq!(bus.len(), 4096);
+        assert_eq!(bus.peripheral_mut().reads, 2);
+    }
+
+    #[test]
+    fn spi_ram() {
+        let cs = [
+            PinTransaction::set(PinState::High),
+            PinTransaction::set(PinState::Low),
+            PinTransaction::set(PinState::High),
+            PinTransaction::set(PinState::Low),
+            PinTransaction::set(PinState::High),
+        ];
+        let spi = [
+            SpiTransaction::write(vec![0x02, 0x00, 0x12, 0x34]),
+            SpiTransaction::write(vec![0xab, 0xcd]),
+            SpiTransaction::write(vec![0x03, 0x00, 0x12, 0x34]),
+            SpiTransaction::transfer(vec![0x00], vec![0xab]),
+        ];
+        let mut ram = SpiRam::new(MockSpi::new(&spi), MockPin::new(&cs), 0x20000, 3).unwrap();
+        ram.write_slice(0x1234, &[0xab, 0xcd]);
+        assert_eq!(ram.read(0x1234), 0xab);
+        assert!(!ram.faulted());
+        let (mut spi, mut cs) = ram.release();
+        spi.done();
+        cs.done();
+    }
+
+    #[test]
+    fn spi_ram_fault() {
+        let err = MockError::Io(ErrorKind::NotConnected);
+        let cs = [
+            PinTransaction::set(PinState::High),
+            PinTransaction::set(PinState::Low).with_error(err.clone()),
+            PinTransaction::set(PinState::High),
+        ];
+        let spi = [];
+        let mut ram = SpiRam::new(MockSpi::new(&spi), MockPin::new(&cs), 0x8000, 2).unwrap();
+        assert_eq!(ram.read(0x10), 0);
+        assert!(ram.faulted());
+        assert_eq!(ram.take_error(), Some(SpiRamError::ChipSelect(err)));
+        assert!(!ram.faulted());
+    }
 }